@@ -46,7 +46,7 @@ pub fn wcloud(c: &mut Criterion) {
 
                 let raw: [u8; 3] = rgb.into_format().into();
 
-                Rgba([raw[0], raw[1], raw[2], 1])
+                Rgba([raw[0], raw[1], raw[2], 255])
             };
 
             word_cloud.generate_from_text_with_color_func(
@@ -0,0 +1,195 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use image::imageops::{self, FilterType};
+use image::{ImageFormat, Rgba, RgbaImage};
+use std::io::Cursor;
+use svg::node::element::{Definitions, Image, LinearGradient, RadialGradient, Rectangle, Stop};
+use svg::{Document, Node};
+
+use crate::color;
+
+/// What the words of a word cloud are drawn on top of
+///
+/// Gradient coordinates are relative to the size of the word cloud, from (0, 0) at the top
+/// left to (1, 1) at the bottom right, so radial gradients are stretched into ellipses on word
+/// clouds that aren't square. Gradient stops are offsets from 0 to 1 with their colors, in order
+#[derive(Clone, Debug, PartialEq)]
+pub enum Background {
+    Color(Rgba<u8>),
+    LinearGradient {
+        start: (f32, f32),
+        end: (f32, f32),
+        stops: Vec<(f32, Rgba<u8>)>,
+    },
+    RadialGradient {
+        center: (f32, f32),
+        radius: f32,
+        stops: Vec<(f32, Rgba<u8>)>,
+    },
+    /// An image stretched to the size of the word cloud, like a photo or the mask image
+    Image(RgbaImage),
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Background::Color(Rgba([0, 0, 0, 255]))
+    }
+}
+
+impl Background {
+    /// Renders the background at the size of the final image
+    pub(crate) fn render(&self, width: u32, height: u32) -> RgbaImage {
+        match self {
+            Background::Color(color) => RgbaImage::from_pixel(width, height, *color),
+            Background::LinearGradient { start, end, stops } => {
+                let direction = (end.0 - start.0, end.1 - start.1);
+                let length_squared = direction.0.powi(2) + direction.1.powi(2);
+
+                gradient_image(width, height, stops, |x, y| {
+                    // How far along the line from the start to the end the point is projected
+                    let t = (x - start.0) * direction.0 + (y - start.1) * direction.1;
+                    if length_squared > 0.0 {
+                        t / length_squared
+                    } else {
+                        0.0
+                    }
+                })
+            }
+            Background::RadialGradient { center, radius, stops } => {
+                gradient_image(width, height, stops, |x, y| {
+                    ((x - center.0).powi(2) + (y - center.1).powi(2)).sqrt()
+                        / radius.max(f32::EPSILON)
+                })
+            }
+            Background::Image(image) if image.dimensions() == (width, height) => image.clone(),
+            Background::Image(image) => {
+                imageops::resize(image, width, height, FilterType::CatmullRom)
+            }
+        }
+    }
+
+    /// Adds the background to the document, underneath anything added after it
+    pub(crate) fn add_to_svg(&self, document: &mut Document, width: u32, height: u32) {
+        let rect = || Rectangle::new().set("width", width).set("height", height);
+
+        match self {
+            Background::Color(color) => {
+                document.assign("style", format!("background-color: {};", color::css_rgba(*color)));
+            }
+            Background::LinearGradient { start, end, stops } => {
+                let gradient = LinearGradient::new()
+                    .set("x1", start.0)
+                    .set("y1", start.1)
+                    .set("x2", end.0)
+                    .set("y2", end.1);
+
+                document.append(Definitions::new().add(add_stops(gradient, stops)));
+                document.append(rect().set("fill", "url(#background)"));
+            }
+            Background::RadialGradient { center, radius, stops } => {
+                let gradient =
+                    RadialGradient::new().set("cx", center.0).set("cy", center.1).set("r", *radius);
+
+                document.append(Definitions::new().add(add_stops(gradient, stops)));
+                document.append(rect().set("fill", "url(#background)"));
+            }
+            Background::Image(image) => {
+                let mut png = Vec::new();
+                image
+                    .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+                    .expect("Unable to encode the background image");
+
+                document.append(
+                    Image::new()
+                        .set("href", format!("data:image/png;base64,{}", STANDARD.encode(png)))
+                        .set("width", width)
+                        .set("height", height)
+                        .set("preserveAspectRatio", "none"),
+                );
+            }
+        }
+    }
+
+    /// The color behind the words, or transparent if there isn't a single one
+    #[cfg(feature = "visualize")]
+    pub(crate) fn color(&self) -> Rgba<u8> {
+        match self {
+            Background::Color(color) => *color,
+            _ => Rgba([0, 0, 0, 0]),
+        }
+    }
+}
+
+fn add_stops<T: Node>(mut gradient: T, stops: &[(f32, Rgba<u8>)]) -> T {
+    gradient.assign("id", "background");
+
+    for (offset, color) in stops {
+        let [r, g, b, a] = color.0;
+        gradient.append(
+            Stop::new()
+                .set("offset", *offset)
+                .set("stop-color", format!("rgb({},{},{})", r, g, b))
+                .set("stop-opacity", a as f32 / 255.0),
+        );
+    }
+
+    gradient
+}
+
+/// Fills an image with the gradient, where `position` gives how far along the gradient a point
+/// is, with coordinates relative to the size of the image
+fn gradient_image(
+    width: u32,
+    height: u32,
+    stops: &[(f32, Rgba<u8>)],
+    position: impl Fn(f32, f32) -> f32,
+) -> RgbaImage {
+    assert!(!stops.is_empty(), "The gradient has no stops");
+
+    RgbaImage::from_fn(width, height, |x, y| {
+        let t = position((x as f32 + 0.5) / width as f32, (y as f32 + 0.5) / height as f32);
+        sample_gradient(stops, t)
+    })
+}
+
+fn sample_gradient(stops: &[(f32, Rgba<u8>)], t: f32) -> Rgba<u8> {
+    let next = stops.iter().position(|(offset, _)| *offset > t);
+
+    match next {
+        Some(0) => stops[0].1,
+        None => stops[stops.len() - 1].1,
+        Some(i) => {
+            let ((from_offset, from), (to_offset, to)) = (stops[i - 1], stops[i]);
+            let fraction = (t - from_offset) / (to_offset - from_offset);
+
+            Rgba(std::array::from_fn(|c| {
+                (from.0[c] as f32 + (to.0[c] as f32 - from.0[c] as f32) * fraction).round() as u8
+            }))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gradients_interpolate_stops() {
+        let stops = vec![(0.0, Rgba([0, 0, 0, 255])), (1.0, Rgba([200, 100, 0, 255]))];
+
+        let linear = Background::LinearGradient { start: (0.0, 0.0), end: (1.0, 0.0), stops };
+        let image = linear.render(4, 2);
+        assert_eq!(image.get_pixel(0, 0), &Rgba([25, 13, 0, 255]));
+        assert_eq!(image.get_pixel(3, 1), &Rgba([175, 88, 0, 255]));
+
+        let radial = Background::RadialGradient {
+            center: (0.5, 0.5),
+            radius: 0.5,
+            stops: vec![(0.5, Rgba([255, 0, 0, 255])), (1.0, Rgba([0, 0, 255, 0]))],
+        };
+        let image = radial.render(10, 10);
+        // Before the first stop and past the last one, the gradient keeps their colors
+        assert_eq!(image.get_pixel(5, 5), &Rgba([255, 0, 0, 255]));
+        assert_eq!(image.get_pixel(0, 0), &Rgba([0, 0, 255, 0]));
+    }
+}
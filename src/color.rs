@@ -0,0 +1,404 @@
+use csscolorparser::Color;
+use image::Rgba;
+use nanorand::{Rng, WyRand};
+use palette::{Hsl, IntoColor, Srgb};
+use regex::{Regex, RegexBuilder};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use crate::{random_color_rgba, Word};
+
+/// A built-in way of coloring the words of a word cloud, used as a color function with
+/// `|word, rng| scheme.color(word, rng)`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum ColorScheme {
+    /// A random hue for every word, like [`random_color_rgba`]
+    #[default]
+    Random,
+    /// A color from the colormap, from the least to the most frequent words
+    Colormap(Colormap, ColorBy),
+    /// A random color from the list for every word
+    Palette(Vec<Rgba<u8>>),
+    /// Random lighter and darker shades of the color
+    Shades(Rgba<u8>),
+    /// Fixed colors for the words in the map, and the other scheme for the rest
+    WordColors(WordColorMap, Box<ColorScheme>),
+}
+
+/// Fixed colors for particular words, which are matched case-insensitively
+#[derive(Clone, Debug, Default)]
+pub struct WordColorMap {
+    words: HashMap<String, Rgba<u8>>,
+    patterns: Vec<(Regex, Rgba<u8>)>,
+}
+
+impl PartialEq for WordColorMap {
+    fn eq(&self, other: &Self) -> bool {
+        self.words == other.words
+            && self.patterns.len() == other.patterns.len()
+            && self
+                .patterns
+                .iter()
+                .zip(&other.patterns)
+                .all(|((a, a_color), (b, b_color))| a.as_str() == b.as_str() && a_color == b_color)
+    }
+}
+
+/// Why a word color map couldn't be created
+#[derive(Debug)]
+pub enum WordColorMapError {
+    /// A pattern isn't a valid regex
+    Pattern(regex::Error),
+    /// A line of the map isn't `word,color`
+    Line(String),
+    /// The color of a word isn't a valid CSS color
+    Color { word: String, color: String },
+}
+
+impl fmt::Display for WordColorMapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WordColorMapError::Pattern(error) => write!(f, "Invalid color map pattern: {}", error),
+            WordColorMapError::Line(line) => {
+                write!(f, "Expected word,color in the color map: {}", line)
+            }
+            WordColorMapError::Color { word, color } => {
+                write!(f, "Invalid color \'{}\' for \'{}\' in the color map", color, word)
+            }
+        }
+    }
+}
+
+impl Error for WordColorMapError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            WordColorMapError::Pattern(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// What places a word along a colormap
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ColorBy {
+    /// The word's frequency relative to the most frequent word
+    #[default]
+    Frequency,
+    /// The word's position among the words by frequency, spreading them evenly over the colormap
+    Rank,
+}
+
+/// Perceptually uniform colormaps, from matplotlib
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Colormap {
+    Viridis,
+    Plasma,
+    Magma,
+    Inferno,
+    Cividis,
+}
+
+impl Colormap {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "viridis" => Some(Colormap::Viridis),
+            "plasma" => Some(Colormap::Plasma),
+            "magma" => Some(Colormap::Magma),
+            "inferno" => Some(Colormap::Inferno),
+            "cividis" => Some(Colormap::Cividis),
+            _ => None,
+        }
+    }
+
+    /// Evenly spaced colors along the colormap, which are interpolated between
+    fn stops(&self) -> &'static [[u8; 3]] {
+        match self {
+            Colormap::Viridis => &[
+                [68, 1, 84],
+                [72, 40, 120],
+                [62, 73, 137],
+                [49, 104, 142],
+                [38, 130, 142],
+                [31, 158, 137],
+                [53, 183, 121],
+                [110, 206, 88],
+                [181, 222, 43],
+                [253, 231, 37],
+            ],
+            Colormap::Plasma => &[
+                [13, 8, 135],
+                [70, 3, 159],
+                [114, 1, 168],
+                [156, 23, 158],
+                [189, 55, 134],
+                [216, 87, 107],
+                [237, 121, 83],
+                [251, 159, 58],
+                [253, 202, 38],
+                [240, 249, 33],
+            ],
+            Colormap::Magma => &[
+                [0, 0, 4],
+                [24, 15, 61],
+                [68, 15, 118],
+                [114, 31, 129],
+                [158, 47, 127],
+                [205, 64, 113],
+                [241, 96, 93],
+                [253, 150, 104],
+                [254, 202, 141],
+                [252, 253, 191],
+            ],
+            Colormap::Inferno => &[
+                [0, 0, 4],
+                [27, 12, 65],
+                [74, 12, 107],
+                [120, 28, 109],
+                [165, 44, 96],
+                [207, 68, 70],
+                [237, 105, 37],
+                [251, 155, 6],
+                [247, 209, 61],
+                [252, 255, 164],
+            ],
+            Colormap::Cividis => &[
+                [0, 34, 78],
+                [18, 53, 112],
+                [59, 73, 108],
+                [87, 93, 109],
+                [112, 113, 115],
+                [138, 135, 121],
+                [166, 157, 117],
+                [196, 181, 108],
+                [228, 207, 91],
+                [254, 232, 56],
+            ],
+        }
+    }
+
+    /// Returns the color at `t`, from 0 at the start of the colormap to 1 at the end
+    pub fn sample(&self, t: f32) -> Rgba<u8> {
+        let stops = self.stops();
+        let position = t.clamp(0.0, 1.0) * (stops.len() - 1) as f32;
+
+        let index = (position as usize).min(stops.len() - 2);
+        let fraction = position - index as f32;
+
+        let (from, to) = (stops[index], stops[index + 1]);
+        let channel =
+            |i: usize| (from[i] as f32 + (to[i] as f32 - from[i] as f32) * fraction).round() as u8;
+
+        Rgba([channel(0), channel(1), channel(2), 255])
+    }
+}
+
+/// Composites the color over the pixel (source-over), with the color's alpha scaled by `coverage`
+pub(crate) fn source_over(pixel: &mut Rgba<u8>, color: Rgba<u8>, coverage: f32) {
+    let src_alpha = color.0[3] as f32 / 255.0 * coverage.clamp(0.0, 1.0);
+    let dst_alpha = pixel.0[3] as f32 / 255.0;
+    let alpha = src_alpha + dst_alpha * (1.0 - src_alpha);
+
+    if alpha <= 0.0 {
+        return;
+    }
+
+    for i in 0..3 {
+        let (src, dst) = (color.0[i] as f32, pixel.0[i] as f32);
+        pixel.0[i] =
+            ((src * src_alpha + dst * dst_alpha * (1.0 - src_alpha)) / alpha).round() as u8;
+    }
+    pixel.0[3] = (alpha * 255.0).round() as u8;
+}
+
+/// Formats the color for CSS, where the alpha is between 0 and 1
+pub(crate) fn css_rgba(color: Rgba<u8>) -> String {
+    let [r, g, b, a] = color.0;
+    format!("rgba({},{},{},{})", r, g, b, a as f32 / 255.0)
+}
+
+impl WordColorMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_word(mut self, word: &str, color: Rgba<u8>) -> Self {
+        self.words.insert(word.to_lowercase(), color);
+        self
+    }
+
+    /// Colors the words the regex matches in full. Words in the map take precedence over
+    /// patterns, which are tried in the order they were added
+    pub fn with_pattern(
+        mut self,
+        pattern: &str,
+        color: Rgba<u8>,
+    ) -> Result<Self, WordColorMapError> {
+        let regex = RegexBuilder::new(&format!("^(?:{})$", pattern))
+            .case_insensitive(true)
+            .build()
+            .map_err(WordColorMapError::Pattern)?;
+
+        self.patterns.push((regex, color));
+        Ok(self)
+    }
+
+    /// Reads a map from lines of `word,color`, where the color is any CSS color. Words
+    /// surrounded by slashes, like `/team \w+/`, are patterns. Empty lines and lines starting
+    /// with `#` are skipped
+    pub fn from_csv(text: &str) -> Result<Self, WordColorMapError> {
+        let mut map = Self::new();
+
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (word, color) =
+                split_color(line).ok_or_else(|| WordColorMapError::Line(line.to_string()))?;
+            let (word, color) = (word.trim(), color.trim());
+
+            let color = match color.parse::<Color>() {
+                Ok(color) => Rgba(color.to_rgba8()),
+                Err(_) => {
+                    return Err(WordColorMapError::Color {
+                        word: word.to_string(),
+                        color: color.to_string(),
+                    })
+                }
+            };
+
+            map = match word.strip_prefix('/').and_then(|word| word.strip_suffix('/')) {
+                Some(pattern) => map.with_pattern(pattern, color)?,
+                None => map.with_word(word, color),
+            };
+        }
+
+        Ok(map)
+    }
+
+    /// Returns the color for the word, if it's in the map or matches one of the patterns
+    pub fn get(&self, word: &str) -> Option<Rgba<u8>> {
+        self.words.get(&word.to_lowercase()).copied().or_else(|| {
+            self.patterns.iter().find(|(regex, _)| regex.is_match(word)).map(|(_, color)| *color)
+        })
+    }
+}
+
+/// Splits a line of a color map at the comma before the color, which is the last one outside of
+/// color functions like rgb(), so patterns can have commas too
+fn split_color(line: &str) -> Option<(&str, &str)> {
+    let mut depth = 0;
+    for (i, c) in line.char_indices().rev() {
+        match c {
+            ')' => depth += 1,
+            '(' => depth -= 1,
+            ',' if depth == 0 => return Some((&line[..i], &line[i + 1..])),
+            _ => {}
+        }
+    }
+
+    None
+}
+
+impl ColorScheme {
+    /// Picks the color of a word, to be used as a word cloud's color function
+    pub fn color(&self, word: &Word, rng: &mut WyRand) -> Rgba<u8> {
+        match self {
+            ColorScheme::Random => random_color_rgba(word, rng),
+            ColorScheme::Colormap(colormap, ColorBy::Frequency) => colormap.sample(word.frequency),
+            ColorScheme::Colormap(colormap, ColorBy::Rank) => colormap.sample(1.0 - word.rank),
+            ColorScheme::Palette(colors) => {
+                assert!(!colors.is_empty(), "The palette has no colors");
+                colors[rng.generate_range(0..colors.len())]
+            }
+            ColorScheme::Shades(color) => {
+                let [r, g, b, a] = color.0;
+                let hsl: Hsl = Srgb::new(r, g, b).into_format::<f32>().into_color();
+
+                let lightness = rng.generate_range(30..=80) as f32 / 100.0;
+                let rgb: Srgb = Hsl::new(hsl.hue, hsl.saturation, lightness).into_color();
+
+                let raw: [u8; 3] = rgb.into_format().into();
+                Rgba([raw[0], raw[1], raw[2], a])
+            }
+            ColorScheme::WordColors(map, fallback) => match map.get(word.text) {
+                Some(color) => color,
+                None => fallback.color(word, rng),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colormap_interpolates_stops() {
+        assert_eq!(Colormap::Viridis.sample(0.0), Rgba([68, 1, 84, 255]));
+        assert_eq!(Colormap::Viridis.sample(1.0), Rgba([253, 231, 37, 255]));
+        assert_eq!(Colormap::Viridis.sample(2.0), Rgba([253, 231, 37, 255]));
+
+        // Halfway between the 5th and 6th stops
+        assert_eq!(Colormap::Plasma.sample(0.5), Rgba([203, 71, 121, 255]));
+        assert_eq!(Colormap::from_name("Magma"), Some(Colormap::Magma));
+        assert_eq!(Colormap::from_name("rainbow"), None);
+    }
+
+    #[test]
+    fn source_over_blends_alpha() {
+        // Anti-aliased edges over a transparent background keep their color
+        let mut pixel = Rgba([0, 0, 0, 0]);
+        source_over(&mut pixel, Rgba([255, 0, 0, 255]), 0.5);
+        assert_eq!(pixel, Rgba([255, 0, 0, 128]));
+
+        let mut pixel = Rgba([255, 255, 255, 255]);
+        source_over(&mut pixel, Rgba([0, 0, 255, 128]), 1.0);
+        assert_eq!(pixel, Rgba([127, 127, 255, 255]));
+
+        let mut pixel = Rgba([0, 255, 0, 128]);
+        source_over(&mut pixel, Rgba([255, 0, 0, 128]), 1.0);
+        assert_eq!(pixel, Rgba([170, 85, 0, 192]));
+
+        assert_eq!(css_rgba(Rgba([1, 2, 3, 255])), "rgba(1,2,3,1)");
+    }
+
+    #[test]
+    fn word_colors_from_csv() {
+        let map = WordColorMap::from_csv(
+            "# Teams\nRed Sox, red\nyankees,rgb(0, 0, 255)\n\n/team \\w+/,#00ff00\n/.*s/,white\n",
+        )
+        .unwrap();
+
+        assert_eq!(map.get("red sox"), Some(Rgba([255, 0, 0, 255])));
+        // Words take precedence over patterns
+        assert_eq!(map.get("YANKEES"), Some(Rgba([0, 0, 255, 255])));
+        assert_eq!(map.get("Team Rocket"), Some(Rgba([0, 255, 0, 255])));
+        // Patterns have to match the whole word
+        assert_eq!(map.get("a team rocket"), None);
+        assert_eq!(map.get("dodgers"), Some(Rgba([255, 255, 255, 255])));
+    }
+
+    #[test]
+    fn word_color_patterns_can_have_commas() {
+        let map = WordColorMap::from_csv("/a{1,2}/,#ff0000\n/b{2,}/, rgb(0, 0, 255)").unwrap();
+
+        assert_eq!(map.get("aa"), Some(Rgba([255, 0, 0, 255])));
+        assert_eq!(map.get("aaa"), None);
+        assert_eq!(map.get("bbb"), Some(Rgba([0, 0, 255, 255])));
+    }
+
+    #[test]
+    fn invalid_word_colors_are_errors() {
+        assert!(matches!(WordColorMap::from_csv("red sox"), Err(WordColorMapError::Line(_))));
+        assert!(matches!(
+            WordColorMap::from_csv("red sox,not a color"),
+            Err(WordColorMapError::Color { .. })
+        ));
+        assert!(matches!(
+            WordColorMap::from_csv("/team (/,red"),
+            Err(WordColorMapError::Pattern(_))
+        ));
+    }
+}
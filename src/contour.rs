@@ -0,0 +1,147 @@
+use image::imageops::{self, FilterType};
+use image::{GrayImage, Luma, Rgba, RgbaImage};
+use svg::node::element::path::Data;
+use svg::node::element::Path;
+
+use crate::{color, mask};
+
+fn is_available(mask: &GrayImage, x: i64, y: i64) -> bool {
+    x >= 0
+        && y >= 0
+        && x < mask.width() as i64
+        && y < mask.height() as i64
+        && mask.get_pixel(x as u32, y as u32).0[0] == 0
+}
+
+/// Finds the available pixels of the mask that border unavailable ones, thickened to `width`
+fn contour_pixels(mask: &GrayImage, width: u32) -> GrayImage {
+    let edges = GrayImage::from_fn(mask.width(), mask.height(), |x, y| {
+        let (x, y) = (x as i64, y as i64);
+        let on_edge = is_available(mask, x, y)
+            && [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)]
+                .iter()
+                .any(|(x, y)| !is_available(mask, *x, *y));
+
+        if on_edge {
+            Luma([255])
+        } else {
+            Luma([0])
+        }
+    });
+
+    mask::filter(&edges, width.saturating_sub(1) / 2, u8::max)
+}
+
+/// Draws the outline of the mask's available space onto the image, which may be a scaled up
+/// version of the mask
+pub fn draw_contour(image: &mut RgbaImage, mask: &GrayImage, width: u32, color: Rgba<u8>) {
+    let resized_mask;
+    let mask = if image.dimensions() != mask.dimensions() {
+        resized_mask = imageops::resize(mask, image.width(), image.height(), FilterType::Nearest);
+        &resized_mask
+    } else {
+        mask
+    };
+
+    for (x, y, px) in contour_pixels(mask, width).enumerate_pixels() {
+        if px.0[0] != 0 {
+            color::source_over(image.get_pixel_mut(x, y), color, 1.0);
+        }
+    }
+}
+
+/// Creates an SVG path following the edges of the mask's available space
+pub fn contour_path(mask: &GrayImage, scale: f32, width: u32, color: Rgba<u8>) -> Path {
+    let mut data = Data::new();
+    let (mask_width, mask_height) = (mask.width() as i64, mask.height() as i64);
+
+    // Joins the pixel edges between available and unavailable pixels into horizontal lines
+    for y in 0..=mask_height {
+        let mut start = None;
+        for x in 0..=mask_width {
+            let on_edge =
+                x < mask_width && is_available(mask, x, y - 1) != is_available(mask, x, y);
+
+            match (on_edge, start) {
+                (true, None) => start = Some(x),
+                (false, Some(start_x)) => {
+                    data = data
+                        .move_to((start_x as f32 * scale, y as f32 * scale))
+                        .horizontal_line_to(x as f32 * scale);
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // ...and vertical ones
+    for x in 0..=mask_width {
+        let mut start = None;
+        for y in 0..=mask_height {
+            let on_edge =
+                y < mask_height && is_available(mask, x - 1, y) != is_available(mask, x, y);
+
+            match (on_edge, start) {
+                (true, None) => start = Some(y),
+                (false, Some(start_y)) => {
+                    data = data
+                        .move_to((x as f32 * scale, start_y as f32 * scale))
+                        .vertical_line_to(y as f32 * scale);
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    stroke(Path::new().set("d", data), width, color)
+}
+
+/// Styles the path as an outline
+pub fn stroke(path: Path, width: u32, color: Rgba<u8>) -> Path {
+    path.set("fill", "none").set("stroke", color::css_rgba(color)).set("stroke-width", width)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square_mask() -> GrayImage {
+        let mut mask = GrayImage::from_pixel(6, 6, Luma([255]));
+        for y in 1..5 {
+            for x in 1..5 {
+                mask.put_pixel(x, y, Luma([0]));
+            }
+        }
+        mask
+    }
+
+    #[test]
+    fn contour_follows_mask_edges() {
+        let contour = contour_pixels(&square_mask(), 1);
+
+        let expected: Vec<u8> = [
+            [0, 0, 0, 0, 0, 0],
+            [0, 1, 1, 1, 1, 0],
+            [0, 1, 0, 0, 1, 0],
+            [0, 1, 0, 0, 1, 0],
+            [0, 1, 1, 1, 1, 0],
+            [0, 0, 0, 0, 0, 0],
+        ]
+        .iter()
+        .flatten()
+        .map(|p| p * 255)
+        .collect();
+
+        assert_eq!(contour.as_raw(), &expected);
+    }
+
+    #[test]
+    fn contour_path_outlines_square() {
+        let path = contour_path(&square_mask(), 2.0, 1, Rgba([0, 0, 0, 255]));
+        let data = path.get_attributes()["d"].to_string();
+
+        assert_eq!(data, "M2,2 H10 M2,10 H10 M2,2 V10 M10,2 V10");
+    }
+}
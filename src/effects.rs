@@ -0,0 +1,255 @@
+use image::imageops;
+use image::{ImageBuffer, Luma, Rgba};
+use svg::node::element::{
+    Filter, FilterEffectComposite, FilterEffectFlood, FilterEffectGaussianBlur, FilterEffectMerge,
+    FilterEffectMergeNode, FilterEffectMorphology, FilterEffectOffset,
+};
+use svg::Node;
+
+/// The coverage of a word's pixels, from 0 to 1
+pub(crate) type Coverage = ImageBuffer<Luma<f32>, Vec<f32>>;
+
+/// An outline around the outside of the glyphs
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stroke {
+    pub width: f32,
+    pub color: Rgba<u8>,
+}
+
+/// A blurred copy of the word behind it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Shadow {
+    pub offset: (f32, f32),
+    /// How far the shadow is blurred, in pixels
+    pub blur: f32,
+    pub color: Rgba<u8>,
+}
+
+/// A soft halo around the word
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Glow {
+    pub radius: f32,
+    pub color: Rgba<u8>,
+}
+
+/// Effects drawn with every word, to keep them legible on busy backgrounds. Sizes are in pixels,
+/// before the word cloud is scaled
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TextEffects {
+    pub stroke: Option<Stroke>,
+    pub shadow: Option<Shadow>,
+    pub glow: Option<Glow>,
+}
+
+impl TextEffects {
+    pub fn is_empty(&self) -> bool {
+        self.stroke.is_none() && self.shadow.is_none() && self.glow.is_none()
+    }
+
+    /// How many pixels the effects reach past the glyphs on any side
+    pub(crate) fn extent(&self) -> u32 {
+        let stroke = self.stroke.map_or(0.0, |stroke| stroke.width);
+        // Blurred edges have all but faded out 2 standard deviations away
+        let shadow = self
+            .shadow
+            .map_or(0.0, |shadow| shadow.offset.0.abs().max(shadow.offset.1.abs()) + shadow.blur);
+        let glow = self.glow.map_or(0.0, |glow| glow.radius);
+
+        (stroke + shadow.max(glow)).ceil() as u32
+    }
+
+    pub(crate) fn scaled(&self, scale: f32) -> Self {
+        TextEffects {
+            stroke: self.stroke.map(|stroke| Stroke { width: stroke.width * scale, ..stroke }),
+            shadow: self.shadow.map(|shadow| Shadow {
+                offset: (shadow.offset.0 * scale, shadow.offset.1 * scale),
+                blur: shadow.blur * scale,
+                ..shadow
+            }),
+            glow: self.glow.map(|glow| Glow { radius: glow.radius * scale, ..glow }),
+        }
+    }
+
+    /// The layers drawn behind the word, from the bottom up, given the coverage of the word
+    /// including its stroke
+    pub(crate) fn background_layers(
+        &self,
+        outlined: &Coverage,
+    ) -> Vec<(Coverage, (i64, i64), Rgba<u8>)> {
+        let mut layers = Vec::new();
+
+        if let Some(shadow) = self.shadow {
+            let offset = (shadow.offset.0.round() as i64, shadow.offset.1.round() as i64);
+            layers.push((blur(outlined, shadow.blur / 2.0), offset, shadow.color));
+        }
+
+        if let Some(glow) = self.glow {
+            let glow_shape = dilate(outlined, glow.radius / 2.0);
+            layers.push((blur(&glow_shape, glow.radius / 4.0), (0, 0), glow.color));
+        }
+
+        layers
+    }
+
+    /// An SVG filter drawing the shadow and glow behind the word, matching the raster renderer.
+    /// The stroke is drawn with the text's own `stroke` attributes instead
+    pub(crate) fn svg_filter(&self, id: &str, width: u32, height: u32) -> Option<Filter> {
+        if self.shadow.is_none() && self.glow.is_none() {
+            return None;
+        }
+
+        // The filter applies in the word cloud's coordinates, so it covers any word
+        let mut filter = Filter::new()
+            .set("id", id)
+            .set("filterUnits", "userSpaceOnUse")
+            .set("x", -(width as i64))
+            .set("y", -(height as i64))
+            .set("width", width * 3)
+            .set("height", height * 3);
+        let mut merge = FilterEffectMerge::new();
+
+        let mut add_layer = |filter: &mut Filter, name: &str, shape: &str, color: Rgba<u8>| {
+            let [r, g, b, a] = color.0;
+            filter.append(
+                FilterEffectFlood::new()
+                    .set("flood-color", format!("rgb({},{},{})", r, g, b))
+                    .set("flood-opacity", a as f32 / 255.0)
+                    .set("result", format!("{}-color", name)),
+            );
+            filter.append(
+                FilterEffectComposite::new()
+                    .set("in", format!("{}-color", name))
+                    .set("in2", shape)
+                    .set("operator", "in")
+                    .set("result", name),
+            );
+            merge.append(FilterEffectMergeNode::new().set("in", name));
+        };
+
+        if let Some(shadow) = self.shadow {
+            filter.append(
+                FilterEffectGaussianBlur::new()
+                    .set("in", "SourceAlpha")
+                    .set("stdDeviation", shadow.blur / 2.0)
+                    .set("result", "shadow-blur"),
+            );
+            filter.append(
+                FilterEffectOffset::new()
+                    .set("in", "shadow-blur")
+                    .set("dx", shadow.offset.0)
+                    .set("dy", shadow.offset.1)
+                    .set("result", "shadow-shape"),
+            );
+            add_layer(&mut filter, "shadow", "shadow-shape", shadow.color);
+        }
+
+        if let Some(glow) = self.glow {
+            filter.append(
+                FilterEffectMorphology::new()
+                    .set("in", "SourceAlpha")
+                    .set("operator", "dilate")
+                    .set("radius", glow.radius / 2.0)
+                    .set("result", "glow-dilated"),
+            );
+            filter.append(
+                FilterEffectGaussianBlur::new()
+                    .set("in", "glow-dilated")
+                    .set("stdDeviation", glow.radius / 4.0)
+                    .set("result", "glow-shape"),
+            );
+            add_layer(&mut filter, "glow", "glow-shape", glow.color);
+        }
+
+        merge.append(FilterEffectMergeNode::new().set("in", "SourceGraphic"));
+        filter.append(merge);
+
+        Some(filter)
+    }
+}
+
+/// Grows the covered pixels outwards by `radius`, anti-aliasing the new edge
+pub(crate) fn dilate(coverage: &Coverage, radius: f32) -> Coverage {
+    if radius <= 0.0 || is_empty(coverage) {
+        return coverage.clone();
+    }
+
+    let reach = radius.ceil() as i64;
+    let (width, height) = (coverage.width() as i64, coverage.height() as i64);
+
+    // The weight of every neighbor within reach, by how much of it is inside the radius
+    let kernel: Vec<_> = (-reach..=reach)
+        .flat_map(|dy| (-reach..=reach).map(move |dx| (dx, dy)))
+        .map(|(dx, dy)| {
+            (dx, dy, (radius + 0.5 - ((dx * dx + dy * dy) as f32).sqrt()).clamp(0.0, 1.0))
+        })
+        .filter(|(_, _, weight)| *weight > 0.0)
+        .collect();
+
+    Coverage::from_fn(coverage.width(), coverage.height(), |x, y| {
+        let value = kernel.iter().fold(0.0f32, |value, (dx, dy, weight)| {
+            let (x, y) = (x as i64 + dx, y as i64 + dy);
+            if x < 0 || y < 0 || x >= width || y >= height {
+                value
+            } else {
+                value.max(coverage.get_pixel(x as u32, y as u32).0[0] * weight)
+            }
+        });
+
+        Luma([value])
+    })
+}
+
+pub(crate) fn blur(coverage: &Coverage, sigma: f32) -> Coverage {
+    if sigma <= 0.0 || is_empty(coverage) {
+        coverage.clone()
+    } else {
+        imageops::blur(coverage, sigma)
+    }
+}
+
+/// Whether the coverage has no pixels, like when a word is drawn entirely off the image
+fn is_empty(coverage: &Coverage) -> bool {
+    coverage.width() == 0 || coverage.height() == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dilate_grows_coverage() {
+        let mut coverage = Coverage::new(9, 9);
+        coverage.put_pixel(4, 4, Luma([1.0]));
+
+        let dilated = dilate(&coverage, 2.0);
+        assert_eq!(dilated.get_pixel(4, 4).0[0], 1.0);
+        assert_eq!(dilated.get_pixel(5, 4).0[0], 1.0);
+        // Pixels on the edge of the radius are partly covered
+        assert_eq!(dilated.get_pixel(6, 4).0[0], 0.5);
+        assert!((0.0..1.0).contains(&dilated.get_pixel(5, 6).0[0]));
+        assert_eq!(dilated.get_pixel(7, 4).0[0], 0.0);
+        assert_eq!(dilated.get_pixel(6, 6).0[0], 0.0);
+    }
+
+    #[test]
+    fn empty_coverage_is_unchanged() {
+        for coverage in [Coverage::new(0, 40), Coverage::new(40, 0)] {
+            assert_eq!(blur(&coverage, 3.0).dimensions(), coverage.dimensions());
+            assert_eq!(dilate(&coverage, 3.0).dimensions(), coverage.dimensions());
+        }
+    }
+
+    #[test]
+    fn extent_covers_effects() {
+        let color = Rgba([0, 0, 0, 255]);
+        let effects = TextEffects {
+            stroke: Some(Stroke { width: 2.0, color }),
+            shadow: Some(Shadow { offset: (3.0, -1.0), blur: 4.0, color }),
+            glow: None,
+        };
+
+        assert_eq!(effects.extent(), 9);
+        assert_eq!(TextEffects::default().extent(), 0);
+        assert_eq!(effects.scaled(2.0).extent(), 18);
+    }
+}
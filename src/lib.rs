@@ -1,17 +1,38 @@
-use ab_glyph::{point, Font, FontVec, Glyph, Point, PxScale};
-use image::{GrayImage, Luma, Rgba, RgbaImage};
+use ab_glyph::{point, Font, FontVec, Glyph, InvalidFont, Point, PxScale, ScaleFont};
+use image::{DynamicImage, GrayImage, Luma, Rgb, Rgba, RgbaImage};
 use palette::{Hsl, IntoColor, Srgb};
+use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
 use std::process::exit;
+use std::time::Instant;
 use svg::{Document, Node};
 use woff2::decode::{convert_woff2_to_ttf, is_woff2};
 
 mod text;
-use text::GlyphData;
+use text::{GlyphData, ShapingFace};
 pub mod sat;
+#[cfg(feature = "shaping")]
+mod shaping;
 mod tokenizer;
 pub use tokenizer::{Tokenizer, DEFAULT_EXCLUDE_WORDS_TEXT};
+mod report;
+pub use report::{DropReason, DroppedWord, GenerationReport, PlacedWordStats};
+mod progress;
+pub use progress::{CancellationToken, ProgressCallback};
+mod mask;
+pub use mask::{MaskChannel, MaskFit, MaskOptions};
+mod background;
+pub use background::Background;
+mod color;
+pub use color::{ColorBy, ColorScheme, Colormap, WordColorMap, WordColorMapError};
+mod contour;
+mod vector;
+pub use vector::{VectorMask, VectorMaskError};
+mod shape;
+pub use shape::MaskShape;
+mod effects;
+pub use effects::{Glow, Shadow, Stroke, TextEffects};
 
 use crate::sat::{Rect, Region};
 use nanorand::{Rng, WyRand};
@@ -26,10 +47,48 @@ pub struct Word<'a> {
     pub font: &'a FontVec,
     pub font_size: PxScale,
     pub glyphs: GlyphData,
-    pub rotated: bool,
+    /// The rotation of the word in degrees, counter-clockwise
+    pub rotation: f32,
+    pub writing_mode: WritingMode,
     pub position: Point,
     pub frequency: f32,
     pub index: usize,
+    /// The position of the word among the words given to the word cloud, from 0 for the most
+    /// frequent to 1 for the least frequent. Pinned words have a rank of 0
+    pub rank: f32,
+    /// Information about the word from outside of the word cloud, see [`WeightedWord::metadata`]
+    pub metadata: Option<&'a WordMetadata>,
+}
+
+/// Information attached to a word, like a sentiment score from an analysis, that is passed along
+/// to color functions and the generation report
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WordMetadata {
+    pub score: Option<f32>,
+    pub category: Option<String>,
+    pub url: Option<String>,
+    /// Any other information, by name
+    pub properties: HashMap<String, String>,
+}
+
+/// A word and its frequency, for generating a word cloud from frequencies instead of text
+#[derive(Clone, Debug, PartialEq)]
+pub struct WeightedWord<'a> {
+    pub text: &'a str,
+    pub frequency: f32,
+    /// Passed along to color functions and the generation report for this word
+    pub metadata: Option<WordMetadata>,
+}
+
+impl<'a> WeightedWord<'a> {
+    pub fn new(text: &'a str, frequency: f32) -> Self {
+        Self { text, frequency, metadata: None }
+    }
+
+    pub fn with_metadata(mut self, value: WordMetadata) -> Self {
+        self.metadata = Some(value);
+        self
+    }
 }
 
 #[derive(Default, PartialEq)]
@@ -53,41 +112,309 @@ pub enum WordCloudImage {
     Svg(Document),
 }
 
+/// The angles a word can be rotated to, in degrees counter-clockwise
+///
+/// Words that aren't rotated (see [`WordCloud::with_word_rotate_chance`]) are always horizontal
+#[derive(Clone, Debug, PartialEq)]
+pub enum WordOrientation {
+    /// Rotated words are given one of these angles at random
+    Angles(Vec<f32>),
+    /// Rotated words are given a random angle between the two bounds
+    Range(f32, f32),
+}
+
+impl Default for WordOrientation {
+    fn default() -> Self {
+        WordOrientation::Angles(vec![90.0])
+    }
+}
+
+/// The direction rotated words are read in
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RotationDirection {
+    /// Words are rotated 90 degrees counter-clockwise
+    BottomToTop,
+    /// Words are rotated 90 degrees clockwise
+    TopToBottom,
+}
+
+impl From<RotationDirection> for WordOrientation {
+    fn from(value: RotationDirection) -> Self {
+        match value {
+            RotationDirection::BottomToTop => WordOrientation::Angles(vec![90.0]),
+            RotationDirection::TopToBottom => WordOrientation::Angles(vec![-90.0]),
+        }
+    }
+}
+
+/// How the glyphs of a word are arranged
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum WritingMode {
+    /// Glyphs are laid out left to right, and the whole word is rotated
+    #[default]
+    Horizontal,
+    /// Glyphs are kept upright and stacked top to bottom, like vertical CJK text
+    Vertical,
+}
+
+/// An area of the word cloud that words are kept out of, like the space for a title or logo.
+/// Coordinates are in pixels, before the word cloud is scaled
+#[derive(Clone, Debug, PartialEq)]
+pub enum KeepOutZone {
+    Rect {
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    },
+    /// A polygon through the points, which is closed automatically
+    Polygon(Vec<(f32, f32)>),
+}
+
+impl KeepOutZone {
+    /// Marks the zone's pixels as unavailable
+    fn stamp(&self, buffer: &mut GrayImage) {
+        let (width, height) = buffer.dimensions();
+
+        match self {
+            KeepOutZone::Rect { x, y, width: zone_width, height: zone_height } => {
+                for y in (*y).min(height)..y.saturating_add(*zone_height).min(height) {
+                    for x in (*x).min(width)..x.saturating_add(*zone_width).min(width) {
+                        buffer.put_pixel(x, y, Luma([255]));
+                    }
+                }
+            }
+            KeepOutZone::Polygon(points) => {
+                assert!(points.len() >= 3, "A keep-out polygon needs at least 3 points");
+
+                let data = points.iter().skip(1).fold(
+                    svg::node::element::path::Data::new().move_to(points[0]),
+                    |data, point| data.line_to(*point),
+                );
+                let zone = VectorMask::from_data(
+                    data.close(),
+                    Some((0.0, 0.0, width as f32, height as f32)),
+                )
+                .rasterize(width, height);
+
+                for (px, zone_px) in buffer.pixels_mut().zip(zone.pixels()) {
+                    if zone_px.0[0] == 0 {
+                        *px = Luma([255]);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A word drawn at a fixed place before the other words, which are placed around it, like a
+/// product name in the middle of the word cloud
+#[derive(Clone, Debug, PartialEq)]
+pub struct PinnedWord {
+    pub text: String,
+    /// The center of the word in pixels, before the word cloud is scaled
+    pub center: (f32, f32),
+    pub font_size: f32,
+    /// The rotation of the word in degrees, counter-clockwise
+    pub rotation: f32,
+    pub metadata: Option<WordMetadata>,
+}
+
+impl WordOrientation {
+    fn random_angle(&self, rng: &mut WyRand) -> f32 {
+        match self {
+            WordOrientation::Angles(angles) if angles.len() == 1 => angles[0],
+            WordOrientation::Angles(angles) => angles[rng.generate_range(0..angles.len())],
+            WordOrientation::Range(min, max) => min + (max - min) * rng.generate::<f32>(),
+        }
+    }
+}
+
 // TODO: Figure out a better way to structure this
 pub enum WordCloudSize {
-    FromDimensions { width: u32, height: u32 },
+    FromDimensions {
+        width: u32,
+        height: u32,
+    },
+    /// Words are placed on the black pixels of the mask
     FromMask(GrayImage),
+    /// Words are placed on every pixel of the mask that isn't pure white, with darker regions
+    /// drawing in the largest words and lighter ones mostly getting small words
+    FromWeightedMask(GrayImage),
+    /// Words are placed inside the shapes of the vector mask, scaled to the width and height
+    FromVectorMask {
+        mask: VectorMask,
+        width: u32,
+        height: u32,
+    },
+    /// Words are placed inside the letters of the text, drawn on a single line as large as it
+    /// fits in the width and height. Uses the word cloud's font when `font` is `None`
+    FromText {
+        text: String,
+        font: Option<FontVec>,
+        width: u32,
+        height: u32,
+    },
+    /// Words are placed inside a built-in shape, drawn at the width and height
+    FromShape {
+        shape: MaskShape,
+        width: u32,
+        height: u32,
+    },
+}
+
+impl WordCloudSize {
+    /// Creates a mask from an image, thresholding soft edges and cleaning it up as configured
+    pub fn from_mask_image(image: &DynamicImage, options: &MaskOptions) -> Self {
+        WordCloudSize::FromMask(options.apply(image))
+    }
+}
+
+/// The space words are placed in, and which parts of it are taken
+#[derive(Clone)]
+struct Canvas {
+    gray_buffer: GrayImage,
+    summed_area_table: Vec<u32>,
+    skip_list: Vec<(usize, usize)>,
+    has_mask: bool,
+    /// How strongly each pixel attracts large words, when using a weighted mask
+    density: Option<GrayImage>,
+    /// The shapes the mask was rasterized from, when using a vector mask
+    vector_mask: Option<VectorMask>,
 }
 
+struct Placement<'a> {
+    words: Vec<Word<'a>>,
+    /// The font size each word had before it was shrunk to find space for it
+    initial_font_sizes: Vec<f32>,
+    /// Why the word after the last placed one couldn't be placed
+    stop_reason: Option<DropReason>,
+    canvas: Canvas,
+    rng: WyRand,
+}
+
+impl Canvas {
+    fn new(gray_buffer: GrayImage, has_mask: bool) -> Self {
+        let mut summed_area_table = vec![0; gray_buffer.len()];
+        u8_to_u32_vec(&gray_buffer, &mut summed_area_table);
+        sat::to_summed_area_table(&mut summed_area_table, gray_buffer.width() as usize, 0);
+
+        let skip_list = create_mask_skip_list(&gray_buffer);
+
+        Canvas {
+            gray_buffer,
+            summed_area_table,
+            skip_list,
+            has_mask,
+            density: None,
+            vector_mask: None,
+        }
+    }
+
+    /// Recomputes the summed-area table after words were drawn into the gray buffer
+    fn update_summed_area_table(&mut self) {
+        u8_to_u32_vec(&self.gray_buffer, &mut self.summed_area_table);
+        sat::to_summed_area_table(
+            &mut self.summed_area_table,
+            self.gray_buffer.width() as usize,
+            0,
+        );
+    }
+}
+
+impl Placement<'_> {
+    /// Describes how the placed words were shrunk, and why the rest of `words` were dropped
+    fn word_stats(&self, words: &[WeightedWord]) -> (Vec<PlacedWordStats>, Vec<DroppedWord>) {
+        let placed_words = self
+            .words
+            .iter()
+            .zip(&self.initial_font_sizes)
+            .map(|(word, initial_font_size)| placed_word_stats(word, *initial_font_size))
+            .collect();
+
+        let dropped_words = words[self.words.len()..]
+            .iter()
+            .enumerate()
+            .map(|(i, word)| DroppedWord {
+                text: word.text.to_string(),
+                reason: match self.stop_reason {
+                    // Less frequent words are never larger, so they would be too small as well
+                    Some(DropReason::BelowMinFontSize) => DropReason::BelowMinFontSize,
+                    Some(DropReason::Cancelled) => DropReason::Cancelled,
+                    Some(reason) if i == 0 => reason,
+                    _ => DropReason::NotAttempted,
+                },
+            })
+            .collect();
+
+        (placed_words, dropped_words)
+    }
+}
+
+/// The maximum number of layouts tried when looking for a font size that fits every word
+const FIT_ALL_WORDS_ITERATIONS: usize = 16;
+
+/// How strongly the largest words are drawn to the darkest parts of a weighted mask
+const DENSITY_FALLOFF: f32 = 10.0;
+
 pub struct WordCloud {
     tokenizer: Tokenizer,
-    background_color: Rgba<u8>,
-    pub font: FontVec,
+    background: Background,
+    /// Set with [`WordCloud::with_font`], so the words are shaped with the new font
+    font: FontVec,
+    /// The font's OpenType tables, if they could be read
+    shaping_face: Option<ShapingFace>,
     min_font_size: f32,
     max_font_size: Option<f32>,
     font_step: f32,
     word_margin: u32,
+    text_effects: TextEffects,
+    supersampling: u32,
     word_rotate_chance: f64,
+    word_orientation: WordOrientation,
+    writing_mode: WritingMode,
     relative_font_scaling: f32,
+    contour_width: u32,
+    contour_color: Rgba<u8>,
+    keep_out_zones: Vec<KeepOutZone>,
+    pinned_words: Vec<PinnedWord>,
+    fit_all_words: bool,
+    progress_callback: Option<ProgressCallback>,
+    cancellation_token: CancellationToken,
     rng_seed: Option<u64>,
+    color_seed: Option<u64>,
     image_type: WordCloudImageType,
 }
 
 impl Default for WordCloud {
     fn default() -> Self {
         let font = FontVec::try_from_vec(include_bytes!("../fonts/Ubuntu-B.ttf").to_vec()).unwrap();
+        let shaping_face = ShapingFace::from_font(&font).ok();
 
         WordCloud {
             tokenizer: Tokenizer::default(),
-            background_color: Rgba([0, 0, 0, 255]),
+            background: Background::default(),
             font,
+            shaping_face,
             min_font_size: 4.0,
             max_font_size: None,
             font_step: 1.0,
             word_margin: 2,
+            text_effects: TextEffects::default(),
+            supersampling: 1,
             word_rotate_chance: 0.10,
+            word_orientation: WordOrientation::default(),
+            writing_mode: WritingMode::default(),
             relative_font_scaling: 0.5,
+            contour_width: 0,
+            contour_color: Rgba([0, 0, 0, 255]),
+            keep_out_zones: Vec::new(),
+            pinned_words: Vec::new(),
+            fit_all_words: false,
+            progress_callback: None,
+            cancellation_token: CancellationToken::default(),
             rng_seed: None,
+            color_seed: None,
             image_type: WordCloudImageType::default(),
         }
     }
@@ -99,14 +426,33 @@ impl WordCloud {
         self
     }
     pub fn with_background_color(mut self, value: Rgba<u8>) -> Self {
-        self.background_color = value;
+        self.background = Background::Color(value);
+        self
+    }
+    /// Sets what the words are drawn on top of, like a gradient or an image
+    pub fn with_background(mut self, value: Background) -> Self {
+        self.background = value;
         self
     }
+    /// Sets the font. With the `shaping` feature, words are laid out a character at a time if
+    /// the font's OpenType tables can't be read, see [`WordCloud::try_with_font`]
     pub fn with_font(mut self, value: FontVec) -> Self {
+        self.shaping_face = ShapingFace::from_font(&value).ok();
         self.font = value;
         self
     }
-    pub fn with_font_from_path(mut self, path: PathBuf) -> Self {
+    /// Sets the font like [`WordCloud::with_font`], returning an error if its OpenType tables
+    /// can't be read to shape the words
+    pub fn try_with_font(mut self, value: FontVec) -> Result<Self, InvalidFont> {
+        self.shaping_face = Some(ShapingFace::from_font(&value)?);
+        self.font = value;
+        Ok(self)
+    }
+    /// The font the words are drawn with
+    pub fn font(&self) -> &FontVec {
+        &self.font
+    }
+    pub fn with_font_from_path(self, path: PathBuf) -> Self {
         let font_file = if path.extension() == Some("woff2".as_ref()) {
             let buffer = fs::read(path).unwrap();
             assert!(is_woff2(&buffer));
@@ -115,9 +461,9 @@ impl WordCloud {
             fs::read(path).expect("Unable to read font file")
         };
 
-        self.font = FontVec::try_from_vec(font_file).expect("Font file may be invalid");
-
-        self
+        FontVec::try_from_vec(font_file)
+            .and_then(|font| self.try_with_font(font))
+            .expect("Font file may be invalid")
     }
     pub fn with_min_font_size(mut self, value: f32) -> Self {
         assert!(value >= 0.0, "The minimum font size for a word cloud cannot be less than 0");
@@ -136,32 +482,107 @@ impl WordCloud {
         self.word_margin = value;
         self
     }
+    /// Sets the outline, shadow and glow drawn with every word. Words are spaced further apart
+    /// to make room for them
+    pub fn with_text_effects(mut self, value: TextEffects) -> Self {
+        self.text_effects = value;
+        self
+    }
+    /// Renders PNG images at this many times their size and shrinks them back down, which
+    /// smooths the edges of small and rotated words. The default of 1 renders them directly, as
+    /// does 0
+    pub fn with_supersampling(mut self, value: u32) -> Self {
+        self.supersampling = value.max(1);
+        self
+    }
     pub fn with_word_rotate_chance(mut self, value: f64) -> Self {
         self.word_rotate_chance = value;
         self
     }
+    pub fn with_word_orientation(mut self, value: WordOrientation) -> Self {
+        if let WordOrientation::Angles(angles) = &value {
+            assert!(!angles.is_empty(), "At least one angle is needed to rotate words");
+        }
+        self.word_orientation = value;
+        self
+    }
+    /// Sets how rotated words are written. With [`WritingMode::Vertical`], rotated words are
+    /// stacked upright instead of being turned to one of the orientation's angles
+    pub fn with_writing_mode(mut self, value: WritingMode) -> Self {
+        self.writing_mode = value;
+        self
+    }
     pub fn with_relative_font_scaling(mut self, value: f32) -> Self {
         assert!((0.0..=1.0).contains(&value), "Relative scaling must be between 0 and 1");
         self.relative_font_scaling = value;
         self
     }
+    /// Sets the width of the outline drawn around the mask, or 0 to not draw one
+    pub fn with_contour_width(mut self, value: u32) -> Self {
+        self.contour_width = value;
+        self
+    }
+    pub fn with_contour_color(mut self, value: Rgba<u8>) -> Self {
+        self.contour_color = value;
+        self
+    }
+    /// Adds an area that words are never placed in. When using a mask, the zone counts as part
+    /// of it, so the mask's contour outlines the zone as well
+    pub fn with_keep_out_zone(mut self, value: KeepOutZone) -> Self {
+        self.keep_out_zones.push(value);
+        self
+    }
+    /// Adds a word that is always drawn at the same place, size and rotation. Pinned words come
+    /// first in the placed words, and aren't taken from the text
+    pub fn with_pinned_word(mut self, value: PinnedWord) -> Self {
+        self.pinned_words.push(value);
+        self
+    }
+    /// Sets whether to shrink the starting font size until every word fits in the word cloud
+    pub fn with_fit_all_words(mut self, value: bool) -> Self {
+        self.fit_all_words = value;
+        self
+    }
+    /// Sets a function to call after each word is placed, with the word's index, its text, and
+    /// the number of words being placed. When fitting all words, the words are placed again for
+    /// every font size that's tried, and the index starts over from 0 with each attempt
+    pub fn with_progress_callback(mut self, value: ProgressCallback) -> Self {
+        self.progress_callback = Some(value);
+        self
+    }
+    /// Sets a token that stops placing words once cancelled. The word cloud is still generated
+    /// with the words placed before that
+    pub fn with_cancellation_token(mut self, value: CancellationToken) -> Self {
+        self.cancellation_token = value;
+        self
+    }
+    /// Sets the seed of the randomness used to lay out the words. Colors have their own seed,
+    /// see [`WordCloud::with_color_seed`]
     pub fn with_rng_seed(mut self, value: u64) -> Self {
         self.rng_seed.replace(value);
         self
     }
+    /// Sets the seed of the randomness passed to color functions, so colors can be changed
+    /// without changing the layout and the other way around. Defaults to the layout's seed
+    pub fn with_color_seed(mut self, value: u64) -> Self {
+        self.color_seed.replace(value);
+        self
+    }
 }
 
 impl WordCloud {
+    /// Renders the words onto the canvas they were placed on, before any words were drawn to it
     fn generate_from_word_positions(
-        rng: &mut WyRand,
-        width: u32,
-        height: u32,
+        &self,
+        canvas: &Canvas,
         word_positions: Vec<Word>,
         scale: f32,
-        background_color: Rgba<u8>,
-        color_func: fn(&Word, &mut WyRand) -> Rgba<u8>,
+        color_func: impl Fn(&Word, &mut WyRand) -> Rgba<u8>,
         image_type: WordCloudImageType,
     ) -> WordCloudImage {
+        let (width, height) = canvas.gray_buffer.dimensions();
+        let mut rng = self.new_color_rng();
+
         // TODO: Refactor this so that we can fail earlier
         if !(0.0..=100.0).contains(&scale) {
             // TODO: Idk if this is good practice
@@ -169,80 +590,195 @@ impl WordCloud {
             exit(1);
         }
 
-        let mut final_image_buffer = RgbaImage::from_pixel(
-            (width as f32 * scale) as u32,
-            (height as f32 * scale) as u32,
-            background_color,
-        );
+        let (scaled_width, scaled_height) =
+            ((width as f32 * scale) as u32, (height as f32 * scale) as u32);
+
+        // PNG images are drawn at the supersampled size and shrunk once everything is drawn
+        let (render_scale, supersampling) = match image_type {
+            WordCloudImageType::Png => (scale * self.supersampling as f32, self.supersampling),
+            WordCloudImageType::Svg => (scale, 1),
+        };
+
+        let mut final_image_buffer = match image_type {
+            WordCloudImageType::Png => {
+                self.background.render(scaled_width * supersampling, scaled_height * supersampling)
+            }
+            WordCloudImageType::Svg => RgbaImage::new(0, 0),
+        };
 
-        use svg::node::element::Text;
+        use svg::node::element::{ClipPath, Definitions, Group, TSpan, Text};
         use svg::Document;
-        let mut document = Document::new()
-            .set(
-                "style",
-                format!(
-                    "background-color: rgba({},{},{},{});",
-                    background_color.0[0],
-                    background_color.0[1],
-                    background_color.0[2],
-                    background_color.0[3]
-                ),
-            )
-            .set("viewBox", (0, 0, (width as f32 * scale) as u32, (height as f32 * scale) as u32))
-            .add(svg::node::element::Style::new(
+        let mut document = Document::new().set("viewBox", (0, 0, scaled_width, scaled_height)).add(
+            svg::node::element::Style::new(
                 "@font-face { font-family: font; src: url(./fonts/Ubuntu-B.ttf); }",
-            ));
+            ),
+        );
+        self.background.add_to_svg(&mut document, scaled_width, scaled_height);
+        let mut words = Group::new();
+
+        let effects = self.text_effects.scaled(render_scale);
+        if let Some(stroke) = effects.stroke {
+            // SVG strokes are centered on the outline, so only the outer half shows under the fill
+            words.assign("stroke", color::css_rgba(stroke.color));
+            words.assign("stroke-width", stroke.width * 2.0);
+            words.assign("stroke-linejoin", "round");
+            words.assign("paint-order", "stroke");
+        }
+        let effects_filter = effects.svg_filter("effects", scaled_width, scaled_height);
+        let has_effects_filter = effects_filter.is_some();
+        if let Some(filter) = effects_filter {
+            document.append(Definitions::new().add(filter));
+        }
 
         for mut word in word_positions.into_iter() {
-            let col = color_func(&word, rng);
+            let col = color_func(&word, &mut rng);
+
+            // Scale where the word was placed rather than the newly laid out glyphs, whose size
+            // is rounded to whole pixels, so words don't drift into each other
+            let transform = text::WordTransform::at(&word.glyphs, word.position, word.rotation)
+                .scaled(render_scale);
 
-            if scale != 1.0 {
-                word.font_size.x *= scale;
-                word.font_size.y *= scale;
+            if render_scale != 1.0 {
+                word.font_size.x *= render_scale;
+                word.font_size.y *= render_scale;
 
-                word.position.x *= scale;
-                word.position.y *= scale;
+                word.position.x *= render_scale;
+                word.position.y *= render_scale;
 
-                word.glyphs = text::text_to_glyphs(word.text, word.font, word.font_size);
+                word.glyphs = text::text_to_glyphs(
+                    word.text,
+                    word.font,
+                    self.shaping_face.as_ref(),
+                    word.font_size,
+                    word.writing_mode,
+                );
             }
 
-            let mut text = Text::new(word.text)
-                .set("fill", format!("rgba({},{},{},{})", col.0[0], col.0[1], col.0[2], col.0[3]))
+            let ascent = word.font.as_scaled(word.font_size).ascent();
+
+            let vertical = word.writing_mode == WritingMode::Vertical;
+            let mut text = Text::new(if vertical { "" } else { word.text })
+                .set("fill", color::css_rgba(col))
                 .set("font-family", "font")
-                .set("font-size", word.font_size.x.max(word.font_size.y))
-                .set("x", word.position.x)
-                .set("y", word.position.y);
+                .set("font-size", word.font_size.x.max(word.font_size.y));
 
-            if word.rotated {
+            if vertical {
+                // Position every character on its own, as support for vertical writing modes varies
+                let cells = text::column_cells(
+                    word.text,
+                    word.font,
+                    self.shaping_face.as_ref(),
+                    word.font_size,
+                );
+                for (cell, position, _) in cells {
+                    text.append(
+                        TSpan::new(cell)
+                            .set("x", word.position.x + position.x)
+                            .set("y", word.position.y + position.y),
+                    );
+                }
+            } else if word.rotation == 0.0 {
+                text.assign("x", word.position.x);
+                text.assign("y", word.position.y + ascent);
+            } else {
+                // Rotate around the center of the word's bounding box, like the raster renderer
+                text.assign("x", 0);
+                text.assign("y", ascent);
                 text.assign(
                     "transform",
                     format!(
-                        "rotate(-90 {}, {}) translate(-{} {})",
-                        word.position.x, word.position.y, word.font_size.y, word.font_size.x,
+                        "translate({} {}) rotate({}) translate({} {})",
+                        transform.center.x,
+                        transform.center.y,
+                        -word.rotation,
+                        -transform.pivot.x,
+                        -transform.pivot.y,
                     ),
                 );
             }
 
-            document.append(text);
+            if has_effects_filter {
+                // Filter each word outside of its own rotation, so shadows all fall the same way
+                words.append(Group::new().set("filter", "url(#effects)").add(text));
+            } else {
+                words.append(text);
+            }
 
             if image_type == WordCloudImageType::Png {
                 text::draw_glyphs_to_rgba_buffer(
                     &mut final_image_buffer,
                     word.glyphs,
                     word.font,
-                    word.position,
-                    word.rotated,
+                    transform,
                     col,
+                    &effects,
                 );
             }
         }
 
+        if let Some(vector_mask) = &canvas.vector_mask {
+            // Clips the words to the mask's shapes, so they don't spill over its crisp edges
+            let clip_path = vector_mask
+                .svg_paths(width, height, scale)
+                .into_iter()
+                .fold(ClipPath::new().set("id", "mask"), |clip_path, path| clip_path.add(path));
+            document.append(Definitions::new().add(clip_path));
+            words.assign("clip-path", "url(#mask)");
+        }
+        document.append(words);
+
+        if canvas.has_mask && self.contour_width > 0 {
+            if image_type == WordCloudImageType::Png {
+                contour::draw_contour(
+                    &mut final_image_buffer,
+                    &canvas.gray_buffer,
+                    self.contour_width * supersampling,
+                    self.contour_color,
+                );
+            }
+
+            match &canvas.vector_mask {
+                Some(vector_mask) => {
+                    for path in vector_mask.svg_paths(width, height, scale) {
+                        document.append(
+                            contour::stroke(path, self.contour_width, self.contour_color)
+                                .set("vector-effect", "non-scaling-stroke"),
+                        );
+                    }
+                }
+                None => document.append(contour::contour_path(
+                    &canvas.gray_buffer,
+                    scale,
+                    self.contour_width,
+                    self.contour_color,
+                )),
+            }
+        }
+
         match image_type {
-            WordCloudImageType::Png => WordCloudImage::Png(final_image_buffer),
+            WordCloudImageType::Png => {
+                WordCloudImage::Png(downsample(&final_image_buffer, supersampling))
+            }
             WordCloudImageType::Svg => WordCloudImage::Svg(document),
         }
     }
 
+    /// Picks the angle and writing mode of a word depending on whether it should be rotated
+    fn word_rotation(&self, should_rotate: bool, rng: &mut WyRand) -> (f32, WritingMode) {
+        match (should_rotate, self.writing_mode) {
+            (false, _) => (0.0, WritingMode::Horizontal),
+            (true, WritingMode::Horizontal) => {
+                (self.word_orientation.random_angle(rng), WritingMode::Horizontal)
+            }
+            (true, WritingMode::Vertical) => (0.0, WritingMode::Vertical),
+        }
+    }
+
+    /// The space kept around each word, including the space its effects need
+    fn word_spacing(&self) -> u32 {
+        self.word_margin + self.text_effects.extent() * 2
+    }
+
     fn check_font_size(font_size: &mut f32, font_step: f32, min_font_size: f32) -> bool {
         let next_font_size = *font_size - font_step;
 
@@ -255,21 +791,25 @@ impl WordCloud {
     }
 
     fn glyphs_height(&self, glyphs: &[Glyph]) -> u32 {
+        // Glyphs like spaces and the joiners in shaped text have no outline
         glyphs
             .iter()
-            .map(|g| {
-                let outlined = self.font.outline_glyph(g.clone()).expect("Unable to outline glyph");
-
-                let bounds = outlined.px_bounds();
-                bounds.height() as u32
-            })
+            .filter_map(|g| self.font.outline_glyph(g.clone()))
+            .map(|outlined| outlined.px_bounds().height() as u32)
             .max()
-            .expect("No glyphs!")
+            .unwrap_or(0)
     }
 
     fn text_dimensions_at_font_size(&self, text: &str, font_size: PxScale) -> Rect {
-        let glyphs = text::text_to_glyphs(text, &self.font, font_size);
-        Rect { width: glyphs.width + self.word_margin, height: glyphs.height + self.word_margin }
+        let glyphs = text::text_to_glyphs(
+            text,
+            &self.font,
+            self.shaping_face.as_ref(),
+            font_size,
+            WritingMode::Horizontal,
+        );
+        let spacing = self.word_spacing();
+        Rect { width: glyphs.width + spacing, height: glyphs.height + spacing }
     }
 
     pub fn generate_from_text(
@@ -287,116 +827,483 @@ impl WordCloud {
         text: &str,
         size: WordCloudSize,
         scale: f32,
-        color_func: fn(&Word, &mut WyRand) -> Rgba<u8>,
+        color_func: impl Fn(&Word, &mut WyRand) -> Rgba<u8>,
         image_type: WordCloudImageType,
     ) -> WordCloudImage {
-        let words = self.tokenizer.get_normalized_word_frequencies(text);
+        self.generate_from_text_with_report(text, size, scale, color_func, image_type).0
+    }
+
+    /// Generates a word cloud like [`WordCloud::generate_from_text_with_color_func`], also
+    /// returning a report of how the words were placed
+    pub fn generate_from_text_with_report(
+        &self,
+        text: &str,
+        size: WordCloudSize,
+        scale: f32,
+        color_func: impl Fn(&Word, &mut WyRand) -> Rgba<u8>,
+        image_type: WordCloudImageType,
+    ) -> (WordCloudImage, GenerationReport) {
+        let (frequencies, _) = self.tokenizer.get_word_frequencies(text);
+        let words: Vec<_> =
+            frequencies.iter().map(|(word, freq)| WeightedWord::new(word, *freq as f32)).collect();
+
+        self.generate_from_frequencies(&words, size, scale, color_func, image_type)
+    }
+
+    /// Generates a word cloud from words and their frequencies, which don't need to be
+    /// normalized, returning a report of how the words were placed
+    pub fn generate_from_frequencies(
+        &self,
+        words: &[WeightedWord],
+        size: WordCloudSize,
+        scale: f32,
+        color_func: impl Fn(&Word, &mut WyRand) -> Rgba<u8>,
+        image_type: WordCloudImageType,
+    ) -> (WordCloudImage, GenerationReport) {
+        let start_time = Instant::now();
+
+        let words = self.tokenizer.normalize_frequencies(words);
 
-        let (mut summed_area_table, mut gray_buffer) = match size {
+        let has_mask = !matches!(size, WordCloudSize::FromDimensions { .. });
+        let mut density = None;
+        let mut vector_mask = None;
+
+        let mut gray_buffer = match size {
             WordCloudSize::FromDimensions { width, height } => {
-                let buf = GrayImage::from_pixel(width, height, Luma([0]));
-                let mut summed_area_table = vec![0; buf.len()];
+                GrayImage::from_pixel(width, height, Luma([0]))
+            }
+            WordCloudSize::FromMask(image) => image,
+            WordCloudSize::FromWeightedMask(image) => {
+                let mask = GrayImage::from_fn(image.width(), image.height(), |x, y| {
+                    if image.get_pixel(x, y).0[0] == 255 {
+                        Luma([255])
+                    } else {
+                        Luma([0])
+                    }
+                });
+                density = Some(image);
+                mask
+            }
+            WordCloudSize::FromVectorMask { mask, width, height } => {
+                let image = mask.rasterize(width, height);
+                vector_mask = Some(mask);
+                image
+            }
+            WordCloudSize::FromText { text, font: Some(font), width, height } => {
+                let face = ShapingFace::from_font(&font).ok();
+                mask::text_mask(&text, &font, face.as_ref(), width, height)
+            }
+            WordCloudSize::FromText { text, font: None, width, height } => {
+                mask::text_mask(&text, &self.font, self.shaping_face.as_ref(), width, height)
+            }
+            WordCloudSize::FromShape { shape, width, height } => {
+                let mask = shape.to_vector_mask(width, height);
+                let image = mask.rasterize(width, height);
+                vector_mask = Some(mask);
+                image
+            }
+        };
+
+        for zone in &self.keep_out_zones {
+            zone.stamp(&mut gray_buffer);
+        }
+
+        let mut canvas = Canvas::new(gray_buffer, has_mask);
+        canvas.density = density;
+        canvas.vector_mask = vector_mask;
 
-                u8_to_u32_vec(&buf, &mut summed_area_table);
-                (summed_area_table, buf)
+        self.send_init_message(&canvas);
+
+        let mut pinned_canvas = canvas.clone();
+        let (pinned_words, dropped_pinned_words) = self.pin_words(&mut pinned_canvas);
+
+        let first_word = words.first().expect("There are no words!");
+        let start_font_size = self.start_font_size(first_word.text, &pinned_canvas);
+
+        let placement =
+            self.place_all_words(&words, start_font_size, &pinned_canvas, self.new_rng());
+        let (mut placed_words, mut dropped_words) = placement.word_stats(&words);
+        placed_words.splice(
+            0..0,
+            pinned_words.iter().map(|word| placed_word_stats(word, word.font_size.y)),
+        );
+        dropped_words.splice(0..0, dropped_pinned_words);
+
+        let coverage = report::coverage(&canvas.gray_buffer, &placement.canvas.gray_buffer);
+
+        let mut words: Vec<_> = pinned_words.into_iter().chain(placement.words).collect();
+        for (index, word) in words.iter_mut().enumerate() {
+            word.index = index;
+        }
+
+        let image =
+            self.generate_from_word_positions(&canvas, words, scale, color_func, image_type);
+
+        let report = GenerationReport {
+            placed_words,
+            dropped_words,
+            cancelled: placement.stop_reason == Some(DropReason::Cancelled),
+            coverage,
+            duration: start_time.elapsed(),
+        };
+
+        (image, report)
+    }
+
+    /// Generates a word cloud from a mask where each distinct color is a region with its own
+    /// words, like each country on a map. Pure white and transparent pixels aren't part of any
+    /// region
+    ///
+    /// The regions are filled one after another, each starting from the most frequent of its
+    /// words, which don't need to be normalized
+    pub fn generate_from_regions<'a>(
+        &'a self,
+        mask: &RgbaImage,
+        regions: &HashMap<Rgb<u8>, Vec<WeightedWord<'a>>>,
+        scale: f32,
+        color_func: impl Fn(&Word, &mut WyRand) -> Rgba<u8>,
+        image_type: WordCloudImageType,
+    ) -> (WordCloudImage, GenerationReport) {
+        let start_time = Instant::now();
+
+        let (width, height) = mask.dimensions();
+        let region_at = |x: u32, y: u32| {
+            let [r, g, b, a] = mask.get_pixel(x, y).0;
+            let color = Rgb([r, g, b]);
+            (a >= 128 && color != Rgb([255, 255, 255]) && regions.contains_key(&color))
+                .then_some(color)
+        };
+
+        let mut all_regions = GrayImage::from_fn(width, height, |x, y| match region_at(x, y) {
+            Some(_) => Luma([0]),
+            None => Luma([255]),
+        });
+        for zone in &self.keep_out_zones {
+            zone.stamp(&mut all_regions);
+        }
+
+        let canvas = Canvas::new(all_regions, true);
+        self.send_init_message(&canvas);
+
+        // Every region's words are stamped into this, so regions never place over each other
+        let mut occupied = canvas.clone();
+        let (mut words, dropped_pinned_words) = self.pin_words(&mut occupied);
+        let mut occupied = occupied.gray_buffer;
+
+        let mut rng = self.new_rng();
+        let mut report = GenerationReport {
+            placed_words: words
+                .iter()
+                .map(|word| placed_word_stats(word, word.font_size.y))
+                .collect(),
+            dropped_words: dropped_pinned_words,
+            ..Default::default()
+        };
+
+        // Fill the regions in a fixed order, so seeded word clouds can be reproduced
+        let mut colors: Vec<_> = regions.keys().copied().collect();
+        colors.sort_by_key(|color| color.0);
+        let all_region_words: Vec<_> = colors
+            .iter()
+            .map(|color| (*color, self.tokenizer.normalize_frequencies(&regions[color])))
+            .collect();
+
+        for (color, region_words) in &all_region_words {
+            let color = *color;
+            if region_words.is_empty() {
+                continue;
+            }
+
+            let region = GrayImage::from_fn(width, height, |x, y| {
+                if occupied.get_pixel(x, y).0[0] == 0 && region_at(x, y) == Some(color) {
+                    Luma([0])
+                } else {
+                    Luma([255])
+                }
+            });
+
+            if !region.pixels().any(|p| p.0[0] == 0) {
+                report.dropped_words.extend(region_words.iter().map(|word| DroppedWord {
+                    text: word.text.to_string(),
+                    reason: DropReason::NoSpace,
+                }));
+                continue;
             }
-            WordCloudSize::FromMask(image) => {
-                let mut table = vec![0; image.len()];
 
-                u8_to_u32_vec(&image, &mut table);
-                sat::to_summed_area_table(&mut table, image.width() as usize, 0);
-                (table, image)
+            let region_canvas = Canvas::new(region, true);
+            let start_font_size = self.start_font_size(region_words[0].text, &region_canvas);
+
+            let placement =
+                self.place_all_words(region_words, start_font_size, &region_canvas, rng.clone());
+            rng = placement.rng.clone();
+
+            for (occupied, px) in occupied.pixels_mut().zip(placement.canvas.gray_buffer.pixels()) {
+                if px.0[0] == 1 {
+                    *occupied = Luma([1]);
+                }
             }
-        };
 
+            let (placed_words, dropped_words) = placement.word_stats(region_words);
+            report.placed_words.extend(placed_words);
+            report.dropped_words.extend(dropped_words);
+            report.cancelled |= placement.stop_reason == Some(DropReason::Cancelled);
+            words.extend(placement.words);
+        }
+
+        for (index, word) in words.iter_mut().enumerate() {
+            word.index = index;
+        }
+
+        report.coverage = report::coverage(&canvas.gray_buffer, &occupied);
+
+        let image =
+            self.generate_from_word_positions(&canvas, words, scale, color_func, image_type);
+
+        report.duration = start_time.elapsed();
+
+        (image, report)
+    }
+
+    /// Lays out the pinned words and draws them into the canvas. Words that reach outside of
+    /// the canvas, or cover a part of it that isn't available, are dropped
+    fn pin_words(&self, canvas: &mut Canvas) -> (Vec<Word<'_>>, Vec<DroppedWord>) {
+        let (width, height) = canvas.gray_buffer.dimensions();
+        let mut words = Vec::new();
+        let mut dropped_words = Vec::new();
+
+        for pinned in &self.pinned_words {
+            let font_size = PxScale::from(pinned.font_size);
+            let glyphs = text::text_to_glyphs(
+                &pinned.text,
+                &self.font,
+                self.shaping_face.as_ref(),
+                font_size,
+                WritingMode::Horizontal,
+            );
+
+            let (rotated_width, rotated_height) =
+                text::rotated_size(glyphs.width, glyphs.height, pinned.rotation);
+            let position = point(
+                pinned.center.0 - rotated_width as f32 / 2.0,
+                pinned.center.1 - rotated_height as f32 / 2.0,
+            );
+
+            let drop = |reason| DroppedWord { text: pinned.text.clone(), reason };
+
+            if position.x < 0.0
+                || position.y < 0.0
+                || position.x as u32 + rotated_width > width
+                || position.y as u32 + rotated_height > height
+            {
+                dropped_words.push(drop(DropReason::OutOfBounds));
+                continue;
+            }
+
+            let mut coverage = GrayImage::new(width, height);
+            text::draw_glyphs_to_gray_buffer(
+                &mut coverage,
+                glyphs.clone(),
+                &self.font,
+                position,
+                pinned.rotation,
+            );
+
+            let obstructed = canvas
+                .gray_buffer
+                .pixels()
+                .zip(coverage.pixels())
+                .any(|(px, covered)| covered.0[0] != 0 && px.0[0] != 0);
+            if obstructed {
+                dropped_words.push(drop(DropReason::Obstructed));
+                continue;
+            }
+            for (px, covered) in canvas.gray_buffer.pixels_mut().zip(coverage.pixels()) {
+                if covered.0[0] != 0 {
+                    *px = Luma([1]);
+                }
+            }
+
+            words.push(Word {
+                text: &pinned.text,
+                font: &self.font,
+                font_size,
+                glyphs,
+                rotation: pinned.rotation,
+                writing_mode: WritingMode::Horizontal,
+                position,
+                frequency: 1.0,
+                index: words.len(),
+                rank: 0.0,
+                metadata: pinned.metadata.as_ref(),
+            });
+        }
+
+        if !words.is_empty() {
+            canvas.update_summed_area_table();
+        }
+
+        (words, dropped_words)
+    }
+
+    fn new_rng(&self) -> WyRand {
+        match self.rng_seed {
+            Some(seed) => WyRand::new_seed(seed),
+            None => WyRand::new(),
+        }
+    }
+
+    fn new_color_rng(&self) -> WyRand {
+        match self.color_seed.or(self.rng_seed) {
+            Some(seed) => WyRand::new_seed(seed),
+            None => WyRand::new(),
+        }
+    }
+
+    #[cfg_attr(not(feature = "visualize"), allow(unused_variables))]
+    fn send_init_message(&self, canvas: &Canvas) {
         #[cfg(feature = "visualize")]
         {
-            let mask = if matches!(WordCloudSize::FromMask, _size) {
-                Some(gray_buffer.to_vec())
-            } else {
-                None
-            };
+            let mask = if canvas.has_mask { Some(canvas.gray_buffer.to_vec()) } else { None };
 
             let serialized = serde_json::to_string(&Message::InitMessage(Init {
-                width: gray_buffer.width(),
-                height: gray_buffer.height(),
+                width: canvas.gray_buffer.width(),
+                height: canvas.gray_buffer.height(),
                 mask,
                 font: self.font.as_slice().to_vec(),
-                background_color: self.background_color.0,
+                background_color: self.background.color().0,
             }))
             .unwrap();
             println!("{}", serialized);
         };
+    }
 
-        let mut final_words = Vec::with_capacity(words.len());
+    /// Estimates the font size that makes the first word about as wide as the canvas, scaled
+    /// down by how much of the mask is available
+    fn start_font_size(&self, first_word: &str, canvas: &Canvas) -> f32 {
+        let gray_buffer = &canvas.gray_buffer;
 
-        let mut last_freq = 1.0;
+        let rect_at_image_height = self.text_dimensions_at_font_size(
+            first_word,
+            PxScale::from(gray_buffer.height() as f32 * 0.95),
+        );
 
-        let mut rng = match self.rng_seed {
-            Some(seed) => WyRand::new_seed(seed),
-            None => WyRand::new(),
-        };
+        let height_ratio = rect_at_image_height.height as f32 / rect_at_image_height.width as f32;
 
-        let first_word = words.first().expect("There are no words!");
+        let mut start_height = gray_buffer.width() as f32 * height_ratio;
 
-        let skip_list = create_mask_skip_list(&gray_buffer);
+        if canvas.has_mask {
+            let black_pixels = gray_buffer.as_raw().iter().filter(|p| **p == 0).count();
+            let available_space: f32 = black_pixels as f32 / gray_buffer.len() as f32;
+            start_height *= available_space;
+        }
 
-        let mut font_size = {
-            let rect_at_image_height = self.text_dimensions_at_font_size(
-                first_word.0,
-                PxScale::from(gray_buffer.height() as f32 * 0.95),
-            );
+        if let Some(max) = self.max_font_size {
+            start_height.min(max)
+        } else {
+            start_height
+        }
+    }
 
-            let height_ratio =
-                rect_at_image_height.height as f32 / rect_at_image_height.width as f32;
+    /// Places the words starting at `start_font_size`, and when fitting all words, searches
+    /// for a starting size that does
+    fn place_all_words<'a>(
+        &'a self,
+        words: &'a [WeightedWord<'a>],
+        start_font_size: f32,
+        canvas: &Canvas,
+        rng: WyRand,
+    ) -> Placement<'a> {
+        let mut placement = self.place_words(words, start_font_size, canvas.clone(), rng.clone());
+
+        if self.fit_all_words && placement.words.len() < words.len() {
+            // Binary search for the largest starting font size that still fits every word,
+            // keeping the layout that placed the most words in case none of them do
+            let mut low = self.min_font_size;
+            let mut high = start_font_size;
+
+            for _ in 0..FIT_ALL_WORDS_ITERATIONS {
+                if high - low <= self.font_step || self.cancellation_token.is_cancelled() {
+                    break;
+                }
 
-            let mut start_height = gray_buffer.width() as f32 * height_ratio;
+                let font_size = (low + high) / 2.0;
+                let attempt = self.place_words(words, font_size, canvas.clone(), rng.clone());
 
-            if matches!(WordCloudSize::FromMask, _size) {
-                let black_pixels = gray_buffer.as_raw().iter().filter(|p| **p == 0).count();
-                let available_space: f32 = black_pixels as f32 / gray_buffer.len() as f32;
-                start_height *= available_space;
-            }
+                if attempt.words.len() == words.len() {
+                    low = font_size;
+                } else {
+                    high = font_size;
+                }
 
-            if let Some(max) = self.max_font_size {
-                start_height.min(max)
-            } else {
-                start_height
+                if attempt.words.len() == words.len() || attempt.words.len() > placement.words.len()
+                {
+                    placement = attempt;
+                }
             }
-        };
+        }
+
+        placement
+    }
+
+    /// Places the words from largest to smallest, starting at `font_size`, until one of them
+    /// can't fit
+    fn place_words<'a>(
+        &'a self,
+        words: &'a [WeightedWord<'a>],
+        mut font_size: f32,
+        mut canvas: Canvas,
+        mut rng: WyRand,
+    ) -> Placement<'a> {
+        let Canvas { gray_buffer, summed_area_table, skip_list, has_mask, density, .. } =
+            &mut canvas;
+        let start_font_size = font_size;
+
+        let mut final_words = Vec::with_capacity(words.len());
+        let mut initial_font_sizes = Vec::with_capacity(words.len());
+        let mut stop_reason = None;
+
+        let mut last_freq = 1.0;
 
-        'outer: for (word, freq) in &words {
+        let last_index = words.len().saturating_sub(1).max(1) as f32;
+
+        'outer: for (word_index, weighted_word) in words.iter().enumerate() {
+            let WeightedWord { text: word, frequency: freq, metadata } = weighted_word;
             if !self.tokenizer.repeat && self.relative_font_scaling != 0.0 {
                 font_size *= self.relative_font_scaling * (freq / last_freq)
                     + (1.0 - self.relative_font_scaling);
             }
 
             if font_size < self.min_font_size {
+                stop_reason = Some(DropReason::BelowMinFontSize);
                 break;
             }
 
             let initial_font_size = font_size;
 
             let mut should_rotate = rng.generate::<u8>() <= (255.0 * self.word_rotate_chance) as u8;
+            let (mut angle, mut writing_mode) = self.word_rotation(should_rotate, &mut rng);
             let mut tried_rotate = false;
             let mut glyphs;
 
-            let has_mask = matches!(WordCloudSize::FromMask, _size);
-
             let pos = loop {
-                glyphs = text::text_to_glyphs(word, &self.font, PxScale::from(font_size));
+                if self.cancellation_token.is_cancelled() {
+                    stop_reason = Some(DropReason::Cancelled);
+                    break 'outer;
+                }
+
+                glyphs = text::text_to_glyphs(
+                    word,
+                    &self.font,
+                    self.shaping_face.as_ref(),
+                    PxScale::from(font_size),
+                    writing_mode,
+                );
                 let _glyphs_height = self.glyphs_height(&glyphs.glyphs);
 
-                let rect = if !should_rotate {
-                    Rect {
-                        width: glyphs.width + self.word_margin,
-                        height: glyphs.height + self.word_margin,
-                    }
-                } else {
-                    Rect {
-                        width: glyphs.height + self.word_margin,
-                        height: glyphs.width + self.word_margin,
-                    }
+                let (width, height) = text::rotated_size(glyphs.width, glyphs.height, angle);
+                let rect = Rect {
+                    width: width + self.word_spacing(),
+                    height: height + self.word_spacing(),
                 };
 
                 #[cfg(feature = "visualize")]
@@ -407,7 +1314,7 @@ impl WordCloud {
                             font_size: font_size as u32,
                             rect_width: rect.width,
                             rect_height: rect.height,
-                            rotation: if should_rotate { 270 } else { 0 },
+                            rotation: (360.0 - angle).rem_euclid(360.0),
                         }))
                         .unwrap();
                     println!("{}", serialized);
@@ -417,82 +1324,80 @@ impl WordCloud {
                     if Self::check_font_size(&mut font_size, self.font_step, self.min_font_size) {
                         continue;
                     } else {
+                        stop_reason = Some(DropReason::NoSpace);
                         break 'outer;
                     };
                 }
 
-                if has_mask {
-                    match sat::find_space_for_rect_masked(
-                        &summed_area_table,
+                let mut picker = sat::PositionPicker::new(&mut rng);
+                if let Some(density) = density {
+                    let size_ratio = initial_font_size / start_font_size;
+                    picker = picker.with_weight(density_weight(density, &rect, size_ratio));
+                }
+
+                let found = if angle % 90.0 != 0.0 {
+                    // Diagonal words cover much less than their bounding box, so only the
+                    // parts they cover need to be free
+                    let footprint =
+                        text::rotated_footprint(&glyphs, &self.font, angle, self.word_spacing());
+                    sat::find_space_for_regions(
+                        summed_area_table,
                         gray_buffer.width(),
                         gray_buffer.height(),
-                        &skip_list,
                         &rect,
-                        &mut rng,
-                    ) {
-                        Some(pos) => {
-                            let half_margin = self.word_margin as f32 / 2.0;
-                            let x = pos.x as f32 + half_margin;
-                            let y = pos.y as f32 + half_margin;
-
-                            break point(x, y);
-                        }
-                        None => {
-                            if !Self::check_font_size(
-                                &mut font_size,
-                                self.font_step,
-                                self.min_font_size,
-                            ) {
-                                if !tried_rotate {
-                                    should_rotate = true;
-                                    tried_rotate = true;
-                                    font_size = initial_font_size;
-                                } else {
-                                    break 'outer;
-                                }
-                            }
-                        }
-                    };
+                        &footprint,
+                        picker,
+                        &self.cancellation_token,
+                    )
+                } else if *has_mask {
+                    sat::find_space_for_rect_masked_cancellable(
+                        summed_area_table,
+                        gray_buffer.width(),
+                        gray_buffer.height(),
+                        skip_list,
+                        &rect,
+                        picker,
+                        &self.cancellation_token,
+                    )
                 } else {
-                    match sat::find_space_for_rect(
-                        &summed_area_table,
+                    sat::find_space_for_rect_cancellable(
+                        summed_area_table,
                         gray_buffer.width(),
                         gray_buffer.height(),
                         &rect,
-                        &mut rng,
-                    ) {
-                        Some(pos) => {
-                            let half_margin = self.word_margin as f32 / 2.0;
-                            let x = pos.x as f32 + half_margin;
-                            let y = pos.y as f32 + half_margin;
-
-                            break point(x, y);
-                        }
-                        None => {
-                            if !Self::check_font_size(
-                                &mut font_size,
-                                self.font_step,
-                                self.min_font_size,
-                            ) {
-                                if !tried_rotate {
-                                    should_rotate = true;
-                                    tried_rotate = true;
-                                    font_size = initial_font_size;
-                                } else {
-                                    break 'outer;
-                                }
+                        picker,
+                        &self.cancellation_token,
+                    )
+                };
+
+                match found {
+                    Some(pos) => {
+                        let half_margin = self.word_spacing() as f32 / 2.0;
+                        let x = pos.x as f32 + half_margin;
+                        let y = pos.y as f32 + half_margin;
+
+                        break point(x, y);
+                    }
+                    None => {
+                        if !Self::check_font_size(
+                            &mut font_size,
+                            self.font_step,
+                            self.min_font_size,
+                        ) {
+                            if !tried_rotate {
+                                should_rotate = !should_rotate;
+                                (angle, writing_mode) = self.word_rotation(should_rotate, &mut rng);
+                                tried_rotate = true;
+                                font_size = initial_font_size;
+                            } else {
+                                stop_reason = Some(DropReason::NoSpace);
+                                break 'outer;
                             }
                         }
-                    };
-                }
+                    }
+                };
             };
-            text::draw_glyphs_to_gray_buffer(
-                &mut gray_buffer,
-                glyphs.clone(),
-                &self.font,
-                pos,
-                should_rotate,
-            );
+            text::draw_glyphs_to_gray_buffer(gray_buffer, glyphs.clone(), &self.font, pos, angle);
 
             #[cfg(feature = "visualize")]
             {
@@ -502,49 +1407,58 @@ impl WordCloud {
                         font_size: font_size as u32,
                         x: pos.x as u32,
                         y: pos.y as u32,
-                        rotation: if should_rotate { 270 } else { 0 },
+                        rotation: (360.0 - angle).rem_euclid(360.0),
                     }))
                     .unwrap();
                 println!("{}", serialized);
             };
 
+            if let Some(progress_callback) = &self.progress_callback {
+                progress_callback(final_words.len(), word, words.len());
+            }
+
+            initial_font_sizes.push(initial_font_size);
             final_words.push(Word {
                 text: word,
                 font: &self.font,
                 font_size: PxScale::from(font_size),
                 glyphs: glyphs.clone(),
-                rotated: should_rotate,
+                rotation: angle,
+                writing_mode,
                 position: pos,
                 frequency: *freq,
                 index: final_words.len(),
+                rank: word_index as f32 / last_index,
+                metadata: metadata.as_ref(),
             });
 
             // TODO: Do a partial sat like the Python implementation
-            u8_to_u32_vec(&gray_buffer, &mut summed_area_table);
+            u8_to_u32_vec(gray_buffer, summed_area_table);
             let start_row = (pos.y - 1.0).min(0.0) as usize;
-            sat::to_summed_area_table(
-                &mut summed_area_table,
-                gray_buffer.width() as usize,
-                start_row,
-            );
+            sat::to_summed_area_table(summed_area_table, gray_buffer.width() as usize, start_row);
 
             last_freq = *freq;
         }
 
-        WordCloud::generate_from_word_positions(
-            &mut rng,
-            gray_buffer.width(),
-            gray_buffer.height(),
-            final_words,
-            scale,
-            self.background_color,
-            color_func,
-            image_type,
-        )
+        Placement { words: final_words, initial_font_sizes, stop_reason, canvas, rng }
     }
 }
 
-fn random_color_rgba(_word: &Word, rng: &mut WyRand) -> Rgba<u8> {
+fn placed_word_stats(word: &Word, initial_font_size: f32) -> PlacedWordStats {
+    let (width, height) = text::rotated_size(word.glyphs.width, word.glyphs.height, word.rotation);
+
+    PlacedWordStats {
+        text: word.text.to_string(),
+        center: (word.position.x + width as f32 / 2.0, word.position.y + height as f32 / 2.0),
+        rotation: word.rotation,
+        initial_font_size,
+        font_size: word.font_size.y,
+        metadata: word.metadata.cloned(),
+    }
+}
+
+/// The default color function, giving every word a random hue
+pub fn random_color_rgba(_word: &Word, rng: &mut WyRand) -> Rgba<u8> {
     let hue: u8 = rng.generate_range(0..255);
     // TODO: Python uses 0.8 for the saturation but it looks too washed out when used here
     //   Maybe something to do with the linear stuff?
@@ -555,7 +1469,43 @@ fn random_color_rgba(_word: &Word, rng: &mut WyRand) -> Rgba<u8> {
 
     let raw: [u8; 3] = rgb.into_format().into();
 
-    Rgba([raw[0], raw[1], raw[2], 1])
+    Rgba([raw[0], raw[1], raw[2], 255])
+}
+
+/// Shrinks the image by averaging every `factor` by `factor` block of pixels. Colors are weighted
+/// by their alpha, so transparent pixels don't darken the edges of words
+fn downsample(image: &RgbaImage, factor: u32) -> RgbaImage {
+    if factor <= 1 {
+        return image.clone();
+    }
+
+    let samples = (factor * factor) as f32;
+
+    RgbaImage::from_fn(image.width() / factor, image.height() / factor, |x, y| {
+        let mut sums = [0.0f32; 4];
+        for dy in 0..factor {
+            for dx in 0..factor {
+                let [r, g, b, a] = image.get_pixel(x * factor + dx, y * factor + dy).0;
+                let alpha = a as f32;
+
+                sums[0] += r as f32 * alpha;
+                sums[1] += g as f32 * alpha;
+                sums[2] += b as f32 * alpha;
+                sums[3] += alpha;
+            }
+        }
+
+        if sums[3] <= 0.0 {
+            return Rgba([0, 0, 0, 0]);
+        }
+
+        Rgba([
+            (sums[0] / sums[3]).round() as u8,
+            (sums[1] / sums[3]).round() as u8,
+            (sums[2] / sums[3]).round() as u8,
+            (sums[3] / samples).round() as u8,
+        ])
+    })
 }
 
 // TODO: This doesn't seem particularly efficient
@@ -604,6 +1554,22 @@ fn _find_image_bounds(img: &GrayImage) -> Region {
     Region { x: min_x, y: min_y, width, height }
 }
 
+/// Weighs positions by how dark a weighted mask is under the middle of `rect`. The closer the
+/// word's size is to the first word's, the more it prefers the darkest regions
+fn density_weight<'a>(
+    density: &'a GrayImage,
+    rect: &Rect,
+    size_ratio: f32,
+) -> impl Fn(u32, u32) -> f32 + 'a {
+    let (half_width, half_height) = (rect.width / 2, rect.height / 2);
+    let exponent = DENSITY_FALLOFF * size_ratio;
+
+    move |x, y| {
+        let value = density.get_pixel(x + half_width, y + half_height).0[0];
+        (1.0 - value as f32 / 255.0).powf(exponent)
+    }
+}
+
 /// Finds the outline of a mask on the x axis
 ///
 /// Useful for skipping white pixels that can't be used when looking for a space to place a word
@@ -618,3 +1584,314 @@ fn create_mask_skip_list(img: &GrayImage) -> Vec<(usize, usize)> {
         })
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    const TEXT: &str = include_str!("../examples/custom_fonts/computer_font_wikipedia.txt");
+
+    #[test]
+    fn cancelling_keeps_placed_words() {
+        let token = CancellationToken::new();
+        let placed = Arc::new(AtomicUsize::new(0));
+
+        let callback_token = token.clone();
+        let callback_placed = placed.clone();
+        let word_cloud = WordCloud::default()
+            .with_rng_seed(0)
+            .with_cancellation_token(token)
+            .with_progress_callback(Box::new(move |index, _word, total| {
+                assert!(index < total);
+                if callback_placed.fetch_add(1, Ordering::Relaxed) == 4 {
+                    callback_token.cancel();
+                }
+            }));
+
+        let (_, report) = word_cloud.generate_from_text_with_report(
+            TEXT,
+            WordCloudSize::FromDimensions { width: 300, height: 200 },
+            1.0,
+            random_color_rgba,
+            WordCloudImageType::Png,
+        );
+
+        assert!(report.cancelled);
+        assert_eq!(placed.load(Ordering::Relaxed), 5);
+        assert_eq!(report.placed_words.len(), 5);
+        assert!(report.dropped_words.iter().all(|w| w.reason == DropReason::Cancelled));
+    }
+
+    #[test]
+    fn fit_all_words_places_every_word() {
+        let generate = |fit_all_words| {
+            let word_cloud = WordCloud::default()
+                .with_tokenizer(Tokenizer::default().with_max_words(40))
+                .with_rng_seed(0)
+                .with_fit_all_words(fit_all_words);
+
+            word_cloud
+                .generate_from_text_with_report(
+                    TEXT,
+                    WordCloudSize::FromDimensions { width: 120, height: 80 },
+                    1.0,
+                    random_color_rgba,
+                    WordCloudImageType::Png,
+                )
+                .1
+        };
+
+        let default = generate(false);
+        assert!(!default.dropped_words.is_empty());
+
+        let fitted = generate(true);
+        assert_eq!(fitted.placed_words.len(), 40);
+        assert!(fitted.dropped_words.is_empty());
+        assert!(fitted.placed_words[0].font_size < default.placed_words[0].font_size);
+    }
+
+    #[test]
+    fn regions_get_their_own_words() {
+        let red = Rgb([255, 0, 0]);
+        let blue = Rgb([0, 0, 255]);
+        let green = Rgb([0, 255, 0]);
+
+        let mask = RgbaImage::from_fn(300, 100, |x, _| match x {
+            0..=139 => Rgba([255, 0, 0, 255]),
+            160..=299 => Rgba([0, 0, 255, 255]),
+            _ => Rgba([255, 255, 255, 255]),
+        });
+        let regions = HashMap::from([
+            (red, vec![WeightedWord::new("apple", 3.0), WeightedWord::new("pear", 1.0)]),
+            (blue, vec![WeightedWord::new("carrot", 2.0)]),
+            (green, vec![WeightedWord::new("grape", 1.0)]),
+        ]);
+
+        let word_cloud = WordCloud::default().with_rng_seed(0);
+        let (_, report) = word_cloud.generate_from_regions(
+            &mask,
+            &regions,
+            1.0,
+            random_color_rgba,
+            WordCloudImageType::Png,
+        );
+
+        let mut placed: Vec<_> = report.placed_words.iter().map(|w| w.text.as_str()).collect();
+        placed.sort();
+        assert_eq!(placed, ["apple", "carrot", "pear"]);
+
+        // The green region isn't in the mask
+        assert_eq!(report.dropped_words.len(), 1);
+        assert_eq!(report.dropped_words[0].text, "grape");
+        assert_eq!(report.dropped_words[0].reason, DropReason::NoSpace);
+    }
+
+    #[test]
+    fn keep_out_zones_are_unavailable() {
+        let mut buffer = GrayImage::new(10, 10);
+        KeepOutZone::Rect { x: 8, y: 1, width: 5, height: 2 }.stamp(&mut buffer);
+        KeepOutZone::Polygon(vec![(0.0, 5.0), (4.0, 5.0), (4.0, 9.0), (0.0, 9.0)])
+            .stamp(&mut buffer);
+
+        let blocked = |x, y| buffer.get_pixel(x, y).0[0] == 255;
+        // The rectangle is clipped to the buffer
+        assert!(blocked(8, 1) && blocked(9, 2));
+        assert!(!blocked(7, 1) && !blocked(8, 3));
+        assert!(blocked(0, 5) && blocked(3, 8));
+        assert!(!blocked(4, 8) && !blocked(0, 4));
+    }
+
+    #[test]
+    fn words_are_placed_around_pinned_words() {
+        let word_cloud = WordCloud::default().with_rng_seed(0).with_pinned_word(PinnedWord {
+            text: String::from("wcloud"),
+            center: (150.0, 100.0),
+            font_size: 60.0,
+            rotation: 0.0,
+            metadata: None,
+        });
+
+        let mut canvas = Canvas::new(GrayImage::new(300, 200), false);
+        let (pinned_words, dropped_words) = word_cloud.pin_words(&mut canvas);
+        assert_eq!(pinned_words.len(), 1);
+        assert!(dropped_words.is_empty());
+        let pinned = canvas.gray_buffer.clone();
+
+        let words: Vec<_> = word_cloud
+            .tokenizer
+            .get_normalized_word_frequencies(TEXT)
+            .into_iter()
+            .map(|(word, freq)| WeightedWord::new(word, freq))
+            .collect();
+        let placement = word_cloud.place_words(&words, 40.0, canvas, WyRand::new_seed(0));
+        assert!(!placement.words.is_empty());
+
+        for word in &placement.words {
+            let (width, height) =
+                text::rotated_size(word.glyphs.width, word.glyphs.height, word.rotation);
+            for y in word.position.y as u32..word.position.y as u32 + height {
+                for x in word.position.x as u32..word.position.x as u32 + width {
+                    assert_eq!(
+                        pinned.get_pixel(x, y).0[0],
+                        0,
+                        "{} covers the pinned word",
+                        word.text
+                    );
+                }
+            }
+        }
+
+        let (_, report) = word_cloud.generate_from_text_with_report(
+            TEXT,
+            WordCloudSize::FromDimensions { width: 300, height: 200 },
+            1.0,
+            random_color_rgba,
+            WordCloudImageType::Png,
+        );
+        assert_eq!(report.placed_words[0].text, "wcloud");
+        assert_eq!(report.placed_words[0].font_size, 60.0);
+        let (x, y) = report.placed_words[0].center;
+        assert!((x - 150.0).abs() <= 1.0 && (y - 100.0).abs() <= 1.0);
+    }
+
+    #[test]
+    fn pinned_words_that_dont_fit_are_dropped() {
+        let pin = |text: &str, center| PinnedWord {
+            text: text.to_string(),
+            center,
+            font_size: 30.0,
+            rotation: 0.0,
+            metadata: None,
+        };
+        let word_cloud = WordCloud::default()
+            .with_rng_seed(0)
+            .with_keep_out_zone(KeepOutZone::Rect { x: 0, y: 0, width: 100, height: 100 })
+            .with_pinned_word(pin("outside", (-10.0, 100.0)))
+            .with_pinned_word(pin("zone", (50.0, 50.0)))
+            .with_pinned_word(pin("fits", (200.0, 100.0)))
+            .with_pinned_word(pin("overlap", (200.0, 100.0)));
+
+        let (_, report) = word_cloud.generate_from_text_with_report(
+            TEXT,
+            WordCloudSize::FromDimensions { width: 300, height: 200 },
+            1.0,
+            random_color_rgba,
+            WordCloudImageType::Png,
+        );
+
+        assert_eq!(report.placed_words[0].text, "fits");
+        let dropped: Vec<_> =
+            report.dropped_words[..3].iter().map(|w| (w.text.as_str(), w.reason)).collect();
+        assert_eq!(
+            dropped,
+            [
+                ("outside", DropReason::OutOfBounds),
+                ("zone", DropReason::Obstructed),
+                ("overlap", DropReason::Obstructed)
+            ]
+        );
+    }
+
+    #[test]
+    fn metadata_reaches_color_functions_and_report() {
+        let metadata = WordMetadata {
+            score: Some(0.8),
+            category: Some(String::from("hardware")),
+            ..Default::default()
+        };
+        // The same text with different metadata stays two separate words
+        let words = [
+            WeightedWord::new("computer", 3.0).with_metadata(metadata.clone()),
+            WeightedWord::new("computer", 2.0),
+            WeightedWord::new("keyboard", 1.0),
+        ];
+        let word_cloud = WordCloud::default().with_rng_seed(0);
+
+        let colored = AtomicUsize::new(0);
+        let (_, report) = word_cloud.generate_from_frequencies(
+            &words,
+            WordCloudSize::FromDimensions { width: 300, height: 200 },
+            1.0,
+            |word, rng| {
+                if let Some(word_metadata) = word.metadata {
+                    assert_eq!(word.frequency, 1.0);
+                    assert_eq!(word_metadata.score, Some(0.8));
+                    colored.fetch_add(1, Ordering::Relaxed);
+                }
+                random_color_rgba(word, rng)
+            },
+            WordCloudImageType::Png,
+        );
+
+        assert_eq!(colored.load(Ordering::Relaxed), 1);
+        assert_eq!(report.placed_words.len(), 3);
+        assert_eq!(report.placed_words[1].text, "computer");
+        assert_eq!(report.placed_words[0].metadata, Some(metadata));
+        assert!(report.placed_words[1..].iter().all(|w| w.metadata.is_none()));
+    }
+
+    #[test]
+    fn color_seed_keeps_layout() {
+        let generate = |color_seed| {
+            let word_cloud = WordCloud::default().with_rng_seed(0).with_color_seed(color_seed);
+            let image = word_cloud.generate_from_text(
+                TEXT,
+                WordCloudSize::FromDimensions { width: 300, height: 200 },
+                1.0,
+                WordCloudImageType::Svg,
+            );
+
+            match image {
+                WordCloudImage::Svg(document) => document.to_string(),
+                WordCloudImage::Png(_) => unreachable!(),
+            }
+        };
+        let without_fills = |svg: &str| {
+            regex::Regex::new("fill=\"[^\"]*\"").unwrap().replace_all(svg, "").to_string()
+        };
+
+        let (first, second) = (generate(1), generate(2));
+        assert_ne!(first, second);
+        assert_eq!(without_fills(&first), without_fills(&second));
+    }
+    #[test]
+    fn scaled_rendering_stays_in_bounds() {
+        let word_cloud = WordCloud::default()
+            .with_rng_seed(0)
+            .with_word_orientation(WordOrientation::Angles(vec![30.0, -60.0]))
+            .with_word_rotate_chance(0.5)
+            .with_supersampling(2);
+
+        for scale in [0.3, 1.7, 2.5] {
+            let image = word_cloud.generate_from_text(
+                TEXT,
+                WordCloudSize::FromDimensions { width: 150, height: 90 },
+                scale,
+                WordCloudImageType::Png,
+            );
+
+            match image {
+                WordCloudImage::Png(image) => {
+                    assert_eq!(image.dimensions(), ((150.0 * scale) as u32, (90.0 * scale) as u32))
+                }
+                WordCloudImage::Svg(_) => unreachable!(),
+            }
+        }
+    }
+
+    #[test]
+    fn downsample_weights_colors_by_alpha() {
+        let mut image = RgbaImage::new(4, 2);
+        image.put_pixel(0, 0, Rgba([255, 0, 0, 255]));
+        image.put_pixel(2, 0, Rgba([0, 0, 255, 255]));
+        image.put_pixel(3, 1, Rgba([0, 0, 255, 255]));
+
+        let small = downsample(&image, 2);
+        assert_eq!(small.dimensions(), (2, 1));
+        // The transparent pixels don't pull the red towards black
+        assert_eq!(small.get_pixel(0, 0), &Rgba([255, 0, 0, 64]));
+        assert_eq!(small.get_pixel(1, 0), &Rgba([0, 0, 255, 128]));
+    }
+}
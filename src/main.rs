@@ -2,12 +2,18 @@ use ab_glyph::FontVec;
 use clap::{arg, command, Parser};
 use csscolorparser::Color;
 use image::codecs::png::PngEncoder;
-use image::{ColorType, ImageEncoder, Rgba};
+use image::{ColorType, GenericImageView, ImageEncoder, Rgba};
 use regex::Regex;
 use std::collections::HashSet;
 use std::fs;
 use std::io::{self, stdout, Read};
-use wcloud::{Tokenizer, WordCloud, WordCloudImageType, WordCloudSize, DEFAULT_EXCLUDE_WORDS_TEXT};
+use std::path::Path;
+use wcloud::{
+    Background, ColorBy, ColorScheme, Colormap, DropReason, GenerationReport, Glow, KeepOutZone,
+    MaskChannel, MaskFit, MaskOptions, MaskShape, PinnedWord, RotationDirection, Shadow, Stroke,
+    TextEffects, Tokenizer, VectorMask, WordCloud, WordCloudImageType, WordCloudSize, WordColorMap,
+    WordOrientation, WritingMode, DEFAULT_EXCLUDE_WORDS_TEXT,
+};
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
@@ -20,22 +26,47 @@ struct Args {
     #[arg(long)]
     regex: Option<String>,
 
-    /// Sets the width of the word cloud
-    #[arg(long, default_value_t = 400)]
-    width: u32,
+    /// Sets the width of the word cloud, scaling the mask to it if there is one [400]
+    #[arg(long)]
+    width: Option<u32>,
 
-    /// Sets the height of the word cloud
-    #[arg(long, default_value_t = 200)]
-    height: u32,
+    /// Sets the height of the word cloud, scaling the mask to it if there is one [200]
+    #[arg(long)]
+    height: Option<u32>,
 
     /// Sets the scale of the final word cloud image, relative to the width and height
     #[arg(long, default_value_t = 1.0)]
     scale: f32,
 
+    /// Renders PNG images at this many times their size and shrinks them back down, smoothing small and rotated words [1]
+    #[arg(long)]
+    supersample: Option<u32>,
+
     /// Sets the background color of the word cloud image
     #[arg(long)]
     background: Option<String>,
 
+    /// Draws the word cloud on top of an image stretched to its size, like a photo or the mask
+    #[arg(long, conflicts_with_all = ["background", "background_gradient"])]
+    background_image: Option<String>,
+
+    /// Draws the word cloud on top of a gradient of comma-separated CSS colors, given as
+    /// linear:COLORS from top to bottom or radial:COLORS from the center outwards
+    #[arg(long, conflicts_with = "background")]
+    background_gradient: Option<String>,
+
+    /// Outlines the words, given as width[:color] [black]
+    #[arg(long)]
+    text_stroke: Option<String>,
+
+    /// Draws a shadow behind the words, given as x,y,blur[:color] [black at half opacity]
+    #[arg(long)]
+    text_shadow: Option<String>,
+
+    /// Draws a glow around the words, given as radius[:color] [white]
+    #[arg(long)]
+    text_glow: Option<String>,
+
     /// Sets the spacing between words
     #[arg(long)]
     margin: Option<u32>,
@@ -56,6 +87,10 @@ struct Args {
     #[arg(long)]
     random_seed: Option<u64>,
 
+    /// Sets the randomness seed for the colors separately, to change them while keeping the layout
+    #[arg(long)]
+    color_seed: Option<u64>,
+
     /// Whether to repeat words until the maximum word count is reached
     #[arg(long, default_value_t = false)]
     repeat: bool,
@@ -68,14 +103,102 @@ struct Args {
     #[arg(long)]
     rotate_chance: Option<f64>,
 
+    /// Sets the angles rotated words can take, as a comma-separated list (90,-90) or a range (-45..45) [90]
+    #[arg(long, allow_hyphen_values = true, conflicts_with = "rotation_direction")]
+    angles: Option<String>,
+
+    /// Sets the direction rotated words are read in (bottom-to-top, top-to-bottom) [bottom-to-top]
+    #[arg(long)]
+    rotation_direction: Option<String>,
+
+    /// Whether to stack the letters of rotated words upright instead of turning the words
+    #[arg(long, default_value_t = false)]
+    vertical_text: bool,
+
     /// Sets how much of an impact word frequency has on the font size of the word (0.0 - 1.0) [0.5]
     #[arg(long)]
     relative_scaling: Option<f32>,
 
-    /// Sets the boolean mask image for the word cloud shape. Any color other than black (#000) means there is no space
+    /// Whether to shrink the words until all of them fit in the word cloud
+    #[arg(long, default_value_t = false)]
+    fit_all: bool,
+
+    /// Whether to print statistics about the placed and dropped words
+    #[arg(long, default_value_t = false)]
+    stats: bool,
+
+    /// Sets the boolean mask image for the word cloud shape. Any color other than black (#000) means there is no space. SVG files are used as vector masks, with words placed inside their shapes, and can't be combined with the other mask options
     #[arg(long)]
     mask: Option<String>,
 
+    /// Uses the text, drawn in the word cloud's font, as the mask
+    #[arg(long, conflicts_with = "mask")]
+    mask_text: Option<String>,
+
+    /// Uses a built-in shape as the mask (circle, ellipse, heart, rounded-rect[:radius], star[:points], polygon[:sides])
+    #[arg(long, conflicts_with_all = ["mask", "mask_text"])]
+    shape: Option<String>,
+
+    /// Sets the brightness below which mask pixels are available for words (0 - 255). The default keeps only black (or fully opaque) pixels available [1]
+    #[arg(long)]
+    mask_threshold: Option<u8>,
+
+    /// Whether light mask pixels are available for words instead of dark ones
+    #[arg(long, default_value_t = false)]
+    mask_invert: bool,
+
+    /// Whether to use the alpha channel of the mask, making opaque pixels available for words
+    #[arg(long, default_value_t = false)]
+    mask_alpha: bool,
+
+    /// Sets how the mask is scaled to the width and height (fit, fill, stretch) [fit]
+    #[arg(long)]
+    mask_fit: Option<String>,
+
+    /// Whether to use the mask's grayscale values as weights, drawing larger words to darker regions. Only pure white pixels are unavailable
+    #[arg(long, default_value_t = false, conflicts_with_all = ["mask_threshold", "mask_alpha", "mask_cleanup"])]
+    mask_weighted: bool,
+
+    /// Sets the radius in pixels of specks and holes to remove from the mask [0]
+    #[arg(long)]
+    mask_cleanup: Option<u32>,
+
+    /// Sets the width of the outline drawn around the mask [0]
+    #[arg(long)]
+    contour_width: Option<u32>,
+
+    /// Sets the color of the outline drawn around the mask [black]
+    #[arg(long)]
+    contour_color: Option<String>,
+
+    /// Colors the words along a colormap (viridis, plasma, magma, inferno, cividis)
+    #[arg(long, conflicts_with_all = ["palette", "shades"])]
+    colormap: Option<String>,
+
+    /// Sets what places the words along the colormap (frequency, rank) [frequency]
+    #[arg(long, requires = "colormap")]
+    color_by: Option<String>,
+
+    /// Colors the words from a comma-separated list of CSS colors
+    #[arg(long, conflicts_with = "shades")]
+    palette: Option<String>,
+
+    /// Colors the words with random lighter and darker shades of a CSS color
+    #[arg(long)]
+    shades: Option<String>,
+
+    /// Gives words fixed colors from a file of word,color lines, where /regex/ words match patterns
+    #[arg(long)]
+    color_map: Option<String>,
+
+    /// Keeps words out of a rectangle given as x,y,width,height in pixels. Can be used multiple times
+    #[arg(long)]
+    keep_out: Vec<String>,
+
+    /// Pins a word centered at a point, given as word@x,y,size[,rotation]. Can be used multiple times
+    #[arg(long)]
+    pin: Vec<String>,
+
     /// A newline-separated list of words to exclude from the word cloud
     #[arg(long)]
     exclude_words: Option<String>,
@@ -93,8 +216,279 @@ struct Args {
     format: String,
 }
 
+fn parse_orientation(value: &str) -> WordOrientation {
+    let parse_angle = |angle: &str| {
+        angle.trim().parse::<f32>().unwrap_or_else(|_| {
+            println!("Invalid angle \'{}\' in --angles", angle.trim());
+            std::process::exit(1)
+        })
+    };
+
+    match value.split_once("..") {
+        Some((min, max)) => WordOrientation::Range(parse_angle(min), parse_angle(max)),
+        None => WordOrientation::Angles(value.split(',').map(parse_angle).collect()),
+    }
+}
+
+fn parse_keep_out_zone(value: &str) -> KeepOutZone {
+    let numbers = value.split(',').map(|n| n.trim().parse::<u32>()).collect::<Result<Vec<_>, _>>();
+
+    match numbers.as_deref() {
+        Ok([x, y, width, height]) => {
+            KeepOutZone::Rect { x: *x, y: *y, width: *width, height: *height }
+        }
+        _ => {
+            println!("Invalid keep-out rectangle \'{}\', expected x,y,width,height", value);
+            std::process::exit(1)
+        }
+    }
+}
+
+fn parse_pinned_word(value: &str) -> PinnedWord {
+    let invalid = || {
+        println!("Invalid pinned word \'{}\', expected word@x,y,size[,rotation]", value);
+        std::process::exit(1)
+    };
+
+    let Some((text, numbers)) = value.rsplit_once('@') else { invalid() };
+    let numbers =
+        numbers.split(',').map(|n| n.trim().parse::<f32>()).collect::<Result<Vec<_>, _>>();
+
+    let (x, y, font_size, rotation) = match numbers.as_deref() {
+        Ok([x, y, font_size]) => (*x, *y, *font_size, 0.0),
+        Ok([x, y, font_size, rotation]) => (*x, *y, *font_size, *rotation),
+        _ => invalid(),
+    };
+
+    if text.is_empty() || font_size <= 0.0 {
+        invalid();
+    }
+
+    PinnedWord { text: text.to_string(), center: (x, y), font_size, rotation, metadata: None }
+}
+
+fn parse_color_scheme(args: &Args) -> ColorScheme {
+    let scheme = parse_base_color_scheme(args);
+
+    match &args.color_map {
+        Some(path) => {
+            let text = fs::read_to_string(path)
+                .unwrap_or_else(|_| panic!("Unable to read color map file \'{}\'", path));
+
+            let map = WordColorMap::from_csv(&text).unwrap_or_else(|error| {
+                println!("{}", error);
+                std::process::exit(1)
+            });
+
+            ColorScheme::WordColors(map, Box::new(scheme))
+        }
+        None => scheme,
+    }
+}
+
+fn parse_base_color_scheme(args: &Args) -> ColorScheme {
+    if let Some(name) = &args.colormap {
+        let colormap = Colormap::from_name(name).unwrap_or_else(|| {
+            println!("Unknown colormap \'{}\'", name);
+            std::process::exit(1)
+        });
+        let color_by = match args.color_by.as_deref() {
+            None | Some("frequency") => ColorBy::Frequency,
+            Some("rank") => ColorBy::Rank,
+            Some(value) => {
+                println!("Invalid value \'{}\' for --color-by, expected frequency or rank", value);
+                std::process::exit(1)
+            }
+        };
+
+        return ColorScheme::Colormap(colormap, color_by);
+    }
+
+    if let Some(color) = &args.shades {
+        let col = color.trim().parse::<Color>().unwrap_or_else(|_| {
+            println!("Invalid color \'{}\' for --shades", color.trim());
+            std::process::exit(1)
+        });
+
+        return ColorScheme::Shades(Rgba(col.to_rgba8()));
+    }
+
+    let Some(palette) = &args.palette else { return ColorScheme::Random };
+    let colors = parse_colors(palette, "the palette");
+
+    ColorScheme::Palette(colors)
+}
+
+/// Parses a comma-separated list of CSS colors
+fn parse_colors(value: &str, context: &str) -> Vec<Rgba<u8>> {
+    // Split on the commas between colors, but not the ones inside functions like rgb()
+    let mut colors = vec![String::new()];
+    let mut depth = 0;
+    for c in value.chars() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                colors.push(String::new());
+                continue;
+            }
+            _ => {}
+        }
+        colors.last_mut().unwrap().push(c);
+    }
+
+    colors
+        .iter()
+        .map(|color| match color.trim().parse::<Color>() {
+            Ok(color) => Rgba(color.to_rgba8()),
+            Err(_) => {
+                println!("Invalid color \'{}\' in {}", color.trim(), context);
+                std::process::exit(1)
+            }
+        })
+        .collect()
+}
+
+/// Parses a text effect given as comma-separated numbers with an optional color after a colon
+fn parse_text_effect(
+    value: &str,
+    name: &str,
+    count: usize,
+    color: Rgba<u8>,
+) -> (Vec<f32>, Rgba<u8>) {
+    let (numbers, color) = match value.split_once(':') {
+        Some((numbers, color)) => {
+            (numbers, color.trim().parse::<Color>().ok().map(|c| Rgba(c.to_rgba8())))
+        }
+        None => (value, Some(color)),
+    };
+    let numbers =
+        numbers.split(',').map(|n| n.trim().parse::<f32>()).collect::<Result<Vec<_>, _>>();
+
+    match (numbers, color) {
+        (Ok(numbers), Some(color))
+            if numbers.len() == count && numbers.iter().all(|n| n.is_finite()) =>
+        {
+            (numbers, color)
+        }
+        _ => {
+            println!("Invalid text {} \'{}\'", name, value);
+            std::process::exit(1)
+        }
+    }
+}
+
+fn parse_background_gradient(value: &str) -> Background {
+    let (kind, colors) = value.split_once(':').unwrap_or(("linear", value));
+    let colors = parse_colors(colors, "the background gradient");
+
+    let last_index = colors.len().saturating_sub(1).max(1) as f32;
+    let stops = colors.into_iter().enumerate().map(|(i, color)| (i as f32 / last_index, color));
+
+    match kind {
+        "linear" => Background::LinearGradient {
+            start: (0.5, 0.0),
+            end: (0.5, 1.0),
+            stops: stops.collect(),
+        },
+        "radial" => {
+            Background::RadialGradient { center: (0.5, 0.5), radius: 0.5, stops: stops.collect() }
+        }
+        _ => {
+            println!("Unknown gradient \'{}\', expected linear or radial", kind);
+            std::process::exit(1)
+        }
+    }
+}
+
+fn parse_shape(value: &str) -> MaskShape {
+    let (name, parameter) = match value.split_once(':') {
+        Some((name, parameter)) => (name, Some(parameter)),
+        None => (value, None),
+    };
+    let invalid = |expected: &str| -> ! {
+        println!("Invalid parameter for the shape \'{}\', expected {}", value, expected);
+        std::process::exit(1)
+    };
+    let count = |default: u32, min: u32, expected: &str| match parameter
+        .map(|parameter| parameter.trim().parse::<u32>())
+    {
+        None => default,
+        Some(Ok(count)) if count >= min => count,
+        Some(_) => invalid(expected),
+    };
+
+    match name {
+        "circle" => MaskShape::Circle,
+        "ellipse" => MaskShape::Ellipse,
+        "heart" => MaskShape::Heart,
+        "rounded-rect" => {
+            let radius = match parameter.map(|parameter| parameter.trim().parse::<f32>()) {
+                None => 0.1,
+                Some(Ok(radius)) if (0.0..=0.5).contains(&radius) => radius,
+                Some(_) => invalid("a corner radius between 0.0 and 0.5"),
+            };
+            MaskShape::RoundedRect { radius }
+        }
+        "star" => MaskShape::Star { points: count(5, 2, "at least 2 points"), inner_radius: 0.5 },
+        "polygon" => MaskShape::Polygon { sides: count(6, 3, "at least 3 sides") },
+        _ => {
+            println!("Unknown shape \'{}\'", name);
+            std::process::exit(1)
+        }
+    }
+}
+
+fn is_svg(path: &str) -> bool {
+    Path::new(path).extension().is_some_and(|extension| extension.eq_ignore_ascii_case("svg"))
+}
+
+fn print_stats(report: &GenerationReport) {
+    let word_count = report.placed_words.len() + report.dropped_words.len();
+
+    println!(
+        "Placed {} of {} words in {}ms",
+        report.placed_words.len(),
+        word_count,
+        report.duration.as_millis()
+    );
+    println!("Words cover {:.1}% of the available space", report.coverage);
+
+    let shrunk_words =
+        report.placed_words.iter().filter(|w| w.font_size_reduction() > 0.0).collect::<Vec<_>>();
+
+    if !shrunk_words.is_empty() {
+        println!("\nShrunk {} words to fit:", shrunk_words.len());
+        for word in shrunk_words {
+            println!(
+                "  {}: {:.1} -> {:.1} (-{:.1})",
+                word.text,
+                word.initial_font_size,
+                word.font_size,
+                word.font_size_reduction()
+            );
+        }
+    }
+
+    if !report.dropped_words.is_empty() {
+        println!("\nDropped {} words:", report.dropped_words.len());
+        for word in &report.dropped_words {
+            let reason = match word.reason {
+                DropReason::BelowMinFontSize => "below the minimum font size",
+                DropReason::NoSpace => "no space left",
+                DropReason::NotAttempted => "not attempted",
+                DropReason::Cancelled => "cancelled",
+                DropReason::OutOfBounds => "outside of the word cloud",
+                DropReason::Obstructed => "covers an unavailable area",
+            };
+            println!("  {} ({})", word.text, reason);
+        }
+    }
+}
+
 fn main() {
     let args = Args::parse();
+    let color_scheme = parse_color_scheme(&args);
     let mut tokenizer = Tokenizer::default();
 
     tokenizer = tokenizer.with_repeat(args.repeat);
@@ -129,13 +523,90 @@ fn main() {
         tokenizer = tokenizer.with_filter(exclude_words);
     }
 
-    let word_cloud_size = match args.mask {
+    let mask_fit = match args.mask_fit.as_deref() {
+        None | Some("fit") => MaskFit::Fit,
+        Some("fill") => MaskFit::Fill,
+        Some("stretch") => MaskFit::Stretch,
+        Some(fit) => {
+            println!("Unknown mask fit \'{}\'", fit);
+            std::process::exit(1)
+        }
+    };
+
+    // A single dimension keeps the mask's aspect ratio
+    let target_size = |(mask_width, mask_height): (u32, u32)| {
+        let aspect_ratio = mask_width as f32 / mask_height as f32;
+        match (args.width, args.height) {
+            (Some(width), Some(height)) => Some((width, height)),
+            (Some(width), None) => Some((width, (width as f32 / aspect_ratio).round() as u32)),
+            (None, Some(height)) => Some(((height as f32 * aspect_ratio).round() as u32, height)),
+            (None, None) => None,
+        }
+        .map(|(width, height)| (width.max(1), height.max(1)))
+    };
+
+    let word_cloud_size = match &args.mask {
+        Some(mask_path) if is_svg(mask_path) => {
+            if args.mask_threshold.is_some()
+                || args.mask_invert
+                || args.mask_alpha
+                || args.mask_weighted
+                || args.mask_cleanup.is_some()
+            {
+                println!("The mask threshold, inversion, alpha, weighting and cleanup options only apply to image masks");
+                std::process::exit(1)
+            }
+
+            let mask = match VectorMask::from_svg_file(mask_path.into()) {
+                Ok(mask) => mask.with_fit(mask_fit),
+                Err(error) => {
+                    println!("{}", error);
+                    std::process::exit(1)
+                }
+            };
+            let (width, height) = target_size(mask.size()).unwrap_or(mask.size());
+
+            WordCloudSize::FromVectorMask { mask, width, height }
+        }
         Some(mask_path) => {
-            let mask_image = image::open(mask_path).unwrap().into_luma8();
+            let mask_image = image::open(mask_path).unwrap();
+
+            let mut options =
+                MaskOptions::default().with_invert(args.mask_invert).with_fit(mask_fit);
+            if let Some((width, height)) = target_size(mask_image.dimensions()) {
+                options = options.with_target_size(width, height);
+            }
 
-            WordCloudSize::FromMask(mask_image)
+            if args.mask_weighted {
+                let mut weights = mask_image.to_luma8();
+                if args.mask_invert {
+                    image::imageops::invert(&mut weights);
+                }
+
+                WordCloudSize::FromWeightedMask(options.resize(&weights, 255))
+            } else {
+                options = options.with_threshold(args.mask_threshold.unwrap_or(1));
+                if args.mask_alpha {
+                    options = options.with_channel(MaskChannel::Alpha);
+                }
+                if let Some(radius) = args.mask_cleanup {
+                    options = options.with_cleanup_radius(radius);
+                }
+
+                WordCloudSize::from_mask_image(&mask_image, &options)
+            }
+        }
+        None => {
+            let (width, height) = (args.width.unwrap_or(400), args.height.unwrap_or(200));
+
+            match (args.mask_text, args.shape) {
+                (Some(text), _) => WordCloudSize::FromText { text, font: None, width, height },
+                (None, Some(shape)) => {
+                    WordCloudSize::FromShape { shape: parse_shape(&shape), width, height }
+                }
+                (None, None) => WordCloudSize::FromDimensions { width, height },
+            }
         }
-        None => WordCloudSize::FromDimensions { width: args.width, height: args.height },
     };
 
     let background_color = match args.background {
@@ -150,6 +621,38 @@ fn main() {
     let mut word_cloud =
         WordCloud::default().with_tokenizer(tokenizer).with_background_color(background_color);
 
+    if let Some(path) = args.background_image {
+        let image = image::open(&path)
+            .unwrap_or_else(|_| panic!("Unable to read background image \'{}\'", path));
+        word_cloud = word_cloud.with_background(Background::Image(image.to_rgba8()));
+    } else if let Some(gradient) = args.background_gradient {
+        word_cloud = word_cloud.with_background(parse_background_gradient(&gradient));
+    }
+
+    let mut text_effects = TextEffects::default();
+    if let Some(stroke) = &args.text_stroke {
+        let (numbers, color) = parse_text_effect(stroke, "stroke", 1, Rgba([0, 0, 0, 255]));
+        text_effects.stroke = Some(Stroke { width: numbers[0], color });
+    }
+    if let Some(shadow) = &args.text_shadow {
+        let (numbers, color) = parse_text_effect(shadow, "shadow", 3, Rgba([0, 0, 0, 128]));
+        text_effects.shadow =
+            Some(Shadow { offset: (numbers[0], numbers[1]), blur: numbers[2], color });
+    }
+    if let Some(glow) = &args.text_glow {
+        let (numbers, color) = parse_text_effect(glow, "glow", 1, Rgba([255, 255, 255, 255]));
+        text_effects.glow = Some(Glow { radius: numbers[0], color });
+    }
+    word_cloud = word_cloud.with_text_effects(text_effects);
+
+    if let Some(supersample) = args.supersample {
+        if supersample == 0 {
+            println!("The supersampling factor must be at least 1");
+            std::process::exit(1);
+        }
+        word_cloud = word_cloud.with_supersampling(supersample);
+    }
+
     if let Some(margin) = args.margin {
         word_cloud = word_cloud.with_word_margin(margin);
     }
@@ -166,6 +669,10 @@ fn main() {
         word_cloud = word_cloud.with_rng_seed(random_seed);
     }
 
+    if let Some(color_seed) = args.color_seed {
+        word_cloud = word_cloud.with_color_seed(color_seed);
+    }
+
     if let Some(font_step) = args.font_step {
         word_cloud = word_cloud.with_font_step(font_step);
     }
@@ -174,11 +681,54 @@ fn main() {
         word_cloud = word_cloud.with_word_rotate_chance(rotate_chance);
     }
 
+    if let Some(direction) = args.rotation_direction {
+        let direction = match direction.as_str() {
+            "bottom-to-top" => RotationDirection::BottomToTop,
+            "top-to-bottom" => RotationDirection::TopToBottom,
+            _ => {
+                println!("Unknown rotation direction \'{}\'", direction);
+                std::process::exit(1)
+            }
+        };
+        word_cloud = word_cloud.with_word_orientation(direction.into());
+    }
+
+    if args.vertical_text {
+        word_cloud = word_cloud.with_writing_mode(WritingMode::Vertical);
+    }
+
+    if let Some(angles) = args.angles {
+        word_cloud = word_cloud.with_word_orientation(parse_orientation(&angles));
+    }
+
+    word_cloud = word_cloud.with_fit_all_words(args.fit_all);
+
+    if let Some(contour_width) = args.contour_width {
+        word_cloud = word_cloud.with_contour_width(contour_width);
+    }
+
+    if let Some(color) = args.contour_color {
+        let col = color.parse::<Color>().unwrap_or(Color::new(0.0, 0.0, 0.0, 1.0)).to_rgba8();
+        word_cloud = word_cloud.with_contour_color(Rgba(col));
+    }
+
+    for zone in &args.keep_out {
+        word_cloud = word_cloud.with_keep_out_zone(parse_keep_out_zone(zone));
+    }
+
+    for pin in &args.pin {
+        word_cloud = word_cloud.with_pinned_word(parse_pinned_word(pin));
+    }
+
     if let Some(font_path) = args.font {
-        let font_file = fs::read(font_path).expect("Unable to read font file");
+        let font_file = fs::read(&font_path).expect("Unable to read font file");
 
-        word_cloud = word_cloud
-            .with_font(FontVec::try_from_vec(font_file).expect("Font file may be invalid"));
+        word_cloud = FontVec::try_from_vec(font_file)
+            .and_then(|font| word_cloud.try_with_font(font))
+            .unwrap_or_else(|_| {
+                println!("Invalid font file \'{}\'", font_path);
+                std::process::exit(1)
+            });
     }
 
     let text = if let Some(text_file_path) = args.text {
@@ -190,13 +740,25 @@ fn main() {
         buffer
     };
 
-    let word_cloud_image = word_cloud.generate_from_text(
+    let (word_cloud_image, report) = word_cloud.generate_from_text_with_report(
         &text,
         word_cloud_size,
         args.scale,
+        |word, rng| color_scheme.color(word, rng),
         WordCloudImageType::from(args.format.clone()),
     );
 
+    if args.stats {
+        print_stats(&report);
+    } else if args.fit_all && !report.dropped_words.is_empty() {
+        let dropped_words = report.dropped_words.iter().map(|w| w.text.as_str());
+        println!(
+            "Unable to fit {} words: {}",
+            report.dropped_words.len(),
+            dropped_words.collect::<Vec<_>>().join(", ")
+        );
+    }
+
     let output = format!("{}.{}", args.output, args.format);
 
     match word_cloud_image {
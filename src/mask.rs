@@ -0,0 +1,350 @@
+use ab_glyph::{point, FontVec, PxScale};
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, GrayImage, Luma};
+
+use crate::text::{self, ShapingFace};
+use crate::WritingMode;
+
+/// The channel of a mask image that decides where words can be placed
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum MaskChannel {
+    /// Dark pixels are available
+    #[default]
+    Luma,
+    /// Opaque pixels are available
+    Alpha,
+}
+
+/// How a mask image is scaled to a target size
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum MaskFit {
+    /// Scales the mask to fit inside the target size, keeping its aspect ratio. The leftover
+    /// space is unavailable
+    #[default]
+    Fit,
+    /// Scales the mask to cover the target size, keeping its aspect ratio and cropping the edges
+    Fill,
+    /// Scales the mask to exactly the target size, ignoring its aspect ratio
+    Stretch,
+}
+
+/// How a mask image is turned into the black and white mask words are placed in
+#[derive(Clone, Debug)]
+pub struct MaskOptions {
+    pub channel: MaskChannel,
+    /// Pixels darker than this (or more opaque, when using the alpha channel) are available
+    pub threshold: u8,
+    /// Whether light (or transparent) pixels are available instead
+    pub invert: bool,
+    /// Removes specks and fills holes up to this radius in pixels, smoothing the mask's edges
+    pub cleanup_radius: u32,
+    /// Scales the mask to this width and height before using it, so words are laid out at the
+    /// target resolution
+    pub target_size: Option<(u32, u32)>,
+    pub fit: MaskFit,
+}
+
+impl Default for MaskOptions {
+    fn default() -> Self {
+        MaskOptions {
+            channel: MaskChannel::Luma,
+            threshold: 128,
+            invert: false,
+            cleanup_radius: 0,
+            target_size: None,
+            fit: MaskFit::Fit,
+        }
+    }
+}
+
+impl MaskOptions {
+    pub fn with_channel(mut self, value: MaskChannel) -> Self {
+        self.channel = value;
+        self
+    }
+    pub fn with_threshold(mut self, value: u8) -> Self {
+        self.threshold = value;
+        self
+    }
+    pub fn with_invert(mut self, value: bool) -> Self {
+        self.invert = value;
+        self
+    }
+    pub fn with_cleanup_radius(mut self, value: u32) -> Self {
+        self.cleanup_radius = value;
+        self
+    }
+    pub fn with_target_size(mut self, width: u32, height: u32) -> Self {
+        assert!(width > 0 && height > 0, "The target size must not be empty");
+        self.target_size = Some((width, height));
+        self
+    }
+    pub fn with_fit(mut self, value: MaskFit) -> Self {
+        self.fit = value;
+        self
+    }
+
+    /// Scales a grayscale image to the target size, if there is one, filling the space left
+    /// over by [`MaskFit::Fit`] with `padding`
+    pub fn resize(&self, image: &GrayImage, padding: u8) -> GrayImage {
+        let Some((width, height)) = self.target_size else {
+            return image.clone();
+        };
+
+        if self.fit == MaskFit::Stretch {
+            return imageops::resize(image, width, height, FilterType::CatmullRom);
+        }
+
+        let scale_x = width as f32 / image.width() as f32;
+        let scale_y = height as f32 / image.height() as f32;
+        let scale = match self.fit {
+            MaskFit::Fill => scale_x.max(scale_y),
+            _ => scale_x.min(scale_y),
+        };
+
+        let scaled_width = ((image.width() as f32 * scale).round() as u32).max(1);
+        let scaled_height = ((image.height() as f32 * scale).round() as u32).max(1);
+        let scaled = imageops::resize(image, scaled_width, scaled_height, FilterType::CatmullRom);
+
+        // Centers the scaled image, cropping or padding it to the target size
+        let offset_x = (width as i64 - scaled_width as i64) / 2;
+        let offset_y = (height as i64 - scaled_height as i64) / 2;
+
+        let mut resized = GrayImage::from_pixel(width, height, Luma([padding]));
+        imageops::replace(&mut resized, &scaled, offset_x, offset_y);
+        resized
+    }
+
+    /// Turns the image into a mask where black pixels are available and white pixels aren't
+    pub fn apply(&self, image: &DynamicImage) -> GrayImage {
+        let values = match self.channel {
+            MaskChannel::Luma => image.to_luma8(),
+            MaskChannel::Alpha => {
+                let alpha = image.to_luma_alpha8();
+                GrayImage::from_fn(alpha.width(), alpha.height(), |x, y| {
+                    Luma([255 - alpha.get_pixel(x, y).0[1]])
+                })
+            }
+        };
+
+        // Resamples before thresholding, so the edges of the shape stay smooth when upscaling.
+        // The padding is on the unavailable side of the threshold
+        let padding = if self.invert { 0 } else { 255 };
+        let mut mask = self.resize(&values, padding);
+
+        for px in mask.pixels_mut() {
+            let available = (px.0[0] < self.threshold) != self.invert;
+            *px = if available { Luma([0]) } else { Luma([255]) };
+        }
+
+        if self.cleanup_radius > 0 {
+            // An opening removes small available specks, then a closing fills small holes
+            let radius = self.cleanup_radius;
+            mask = filter(&filter(&mask, radius, u8::max), radius, u8::min);
+            mask = filter(&filter(&mask, radius, u8::min), radius, u8::max);
+        }
+
+        mask
+    }
+}
+
+/// The smallest font size the letters of a text mask are shrunk to while fitting them
+const MIN_TEXT_MASK_FONT_SIZE: f32 = 1.0;
+
+/// Draws the text as large as it fits in a `width` by `height` mask, with its letters available.
+/// Nothing is available when the text has no letters to draw, or they don't fit at any size
+pub(crate) fn text_mask(
+    text: &str,
+    font: &FontVec,
+    face: Option<&ShapingFace>,
+    width: u32,
+    height: u32,
+) -> GrayImage {
+    let mut mask = GrayImage::from_pixel(width, height, Luma([255]));
+
+    let Some(reference) = draw_letters(text, font, face, height as f32) else { return mask };
+    let fit =
+        (width as f32 / reference.width() as f32).min(height as f32 / reference.height() as f32);
+
+    // Rounding can make the letters a pixel too large
+    let mut font_size = height as f32 * fit;
+    let letters = loop {
+        if font_size < MIN_TEXT_MASK_FONT_SIZE {
+            return mask;
+        }
+
+        match draw_letters(text, font, face, font_size) {
+            Some(letters) if letters.width() <= width && letters.height() <= height => {
+                break letters
+            }
+            _ => font_size *= 0.99,
+        }
+    };
+
+    let x = (width - letters.width()) / 2;
+    let y = (height - letters.height()) / 2;
+
+    for (letter_x, letter_y, px) in letters.enumerate_pixels() {
+        if px.0[0] != 0 {
+            mask.put_pixel(x + letter_x, y + letter_y, Luma([0]));
+        }
+    }
+
+    mask
+}
+
+/// Draws the text, cropped to the pixels its letters cover, if they cover any
+fn draw_letters(
+    text: &str,
+    font: &FontVec,
+    face: Option<&ShapingFace>,
+    font_size: f32,
+) -> Option<GrayImage> {
+    let glyphs =
+        text::text_to_glyphs(text, font, face, PxScale::from(font_size), WritingMode::Horizontal);
+
+    let mut buffer = GrayImage::new(glyphs.width, glyphs.height);
+    text::draw_glyphs_to_gray_buffer(&mut buffer, glyphs, font, point(0.0, 0.0), 0.0);
+
+    let covered = buffer.enumerate_pixels().filter(|(_, _, px)| px.0[0] != 0);
+    let (min_x, min_y, max_x, max_y) =
+        covered.fold((u32::MAX, u32::MAX, 0, 0), |(min_x, min_y, max_x, max_y), (x, y, _)| {
+            (min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y))
+        });
+    if min_x > max_x {
+        return None;
+    }
+
+    Some(imageops::crop_imm(&buffer, min_x, min_y, max_x - min_x + 1, max_y - min_y + 1).to_image())
+}
+
+/// Replaces each pixel with the result of `pick` over the square of pixels around it
+pub(crate) fn filter(mask: &GrayImage, radius: u32, pick: fn(u8, u8) -> u8) -> GrayImage {
+    let (width, height) = mask.dimensions();
+
+    let window =
+        |center: u32, len: u32| center.saturating_sub(radius)..=(center + radius).min(len - 1);
+
+    let horizontal = GrayImage::from_fn(width, height, |x, y| {
+        Luma([window(x, width).map(|x| mask.get_pixel(x, y).0[0]).reduce(pick).unwrap()])
+    });
+
+    GrayImage::from_fn(width, height, |x, y| {
+        Luma([window(y, height).map(|y| horizontal.get_pixel(x, y).0[0]).reduce(pick).unwrap()])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{LumaA, Rgba, RgbaImage};
+
+    fn available(mask: &GrayImage) -> Vec<bool> {
+        mask.pixels().map(|p| p.0[0] == 0).collect()
+    }
+
+    #[test]
+    fn threshold_and_invert() {
+        let image =
+            DynamicImage::ImageLuma8(GrayImage::from_raw(4, 1, vec![0, 100, 200, 255]).unwrap());
+
+        let mask = MaskOptions::default().apply(&image);
+        assert_eq!(available(&mask), [true, true, false, false]);
+
+        let mask = MaskOptions::default().with_invert(true).apply(&image);
+        assert_eq!(available(&mask), [false, false, true, true]);
+
+        let mask = MaskOptions::default().with_threshold(1).apply(&image);
+        assert_eq!(available(&mask), [true, false, false, false]);
+    }
+
+    #[test]
+    fn alpha_channel() {
+        let mut image = RgbaImage::from_pixel(2, 1, Rgba([255, 255, 255, 0]));
+        image.put_pixel(1, 0, Rgba([255, 255, 255, 255]));
+
+        let options = MaskOptions::default().with_channel(MaskChannel::Alpha);
+        let mask = options.apply(&DynamicImage::ImageRgba8(image));
+        assert_eq!(available(&mask), [false, true]);
+
+        let image = image::GrayAlphaImage::from_pixel(1, 1, LumaA([0, 255]));
+        let mask = options.with_invert(true).apply(&DynamicImage::ImageLumaA8(image));
+        assert_eq!(available(&mask), [false]);
+    }
+
+    #[test]
+    fn cleanup_removes_specks_and_holes() {
+        let mut image = GrayImage::from_pixel(15, 15, Luma([255]));
+        // A speck of available space outside the shape
+        image.put_pixel(0, 0, Luma([0]));
+        // A square shape with a hole in the middle
+        for y in 2..13 {
+            for x in 2..13 {
+                image.put_pixel(x, y, Luma([0]));
+            }
+        }
+        image.put_pixel(7, 7, Luma([255]));
+
+        let options = MaskOptions::default().with_cleanup_radius(1);
+        let mask = options.apply(&DynamicImage::ImageLuma8(image));
+
+        assert_eq!(mask.get_pixel(0, 0), &Luma([255]));
+        assert_eq!(mask.get_pixel(7, 7), &Luma([0]));
+        assert_eq!(mask.get_pixel(2, 2), &Luma([0]));
+        assert_eq!(mask.get_pixel(1, 7), &Luma([255]));
+    }
+
+    #[test]
+    fn resize_to_target_size() {
+        // A 4x2 image with an available left half
+        let image = GrayImage::from_fn(4, 2, |x, _| if x < 2 { Luma([0]) } else { Luma([255]) });
+        let image = DynamicImage::ImageLuma8(image);
+
+        let options = MaskOptions::default().with_target_size(8, 8);
+        let mask = options.apply(&image);
+        assert_eq!(mask.dimensions(), (8, 8));
+        // Fitting pads the top and bottom with unavailable space
+        assert_eq!(mask.get_pixel(0, 0), &Luma([255]));
+        assert_eq!(mask.get_pixel(0, 4), &Luma([0]));
+        assert_eq!(mask.get_pixel(7, 4), &Luma([255]));
+
+        let mask = options.clone().with_invert(true).apply(&image);
+        assert_eq!(mask.get_pixel(0, 0), &Luma([255]));
+        assert_eq!(mask.get_pixel(7, 4), &Luma([0]));
+
+        // Filling crops the sides, leaving the middle of the image
+        let mask = options.clone().with_fit(MaskFit::Fill).apply(&image);
+        assert_eq!(mask.get_pixel(0, 0), &Luma([0]));
+        assert_eq!(mask.get_pixel(7, 7), &Luma([255]));
+
+        let mask = options.with_fit(MaskFit::Stretch).apply(&image);
+        assert_eq!(mask.get_pixel(0, 0), &Luma([0]));
+        assert_eq!(mask.get_pixel(7, 0), &Luma([255]));
+    }
+
+    #[test]
+    fn text_mask_fits_the_size() {
+        let font = FontVec::try_from_vec(include_bytes!("../fonts/Ubuntu-B.ttf").to_vec()).unwrap();
+        let mask = text_mask("LOVE", &font, None, 200, 100);
+        assert_eq!(mask.dimensions(), (200, 100));
+
+        // The letters span most of the width, and the corners are left out
+        let columns: Vec<u32> =
+            mask.enumerate_pixels().filter(|(_, _, p)| p.0[0] == 0).map(|(x, _, _)| x).collect();
+        assert!(columns.iter().min().unwrap() < &20 && columns.iter().max().unwrap() > &180);
+        assert_eq!(mask.get_pixel(0, 0), &Luma([255]));
+        assert_eq!(mask.get_pixel(199, 99), &Luma([255]));
+    }
+
+    #[test]
+    fn text_mask_without_letters_is_empty() {
+        let font = FontVec::try_from_vec(include_bytes!("../fonts/Ubuntu-B.ttf").to_vec()).unwrap();
+
+        for (text, width, height) in [("", 50, 50), ("  ", 50, 50), ("LOVE", 2, 1), ("LOVE", 0, 0)]
+        {
+            let mask = text_mask(text, &font, None, width, height);
+            assert_eq!(mask.dimensions(), (width, height));
+            assert!(mask.pixels().all(|p| p.0[0] == 255), "{:?} at {}x{}", text, width, height);
+        }
+    }
+}
@@ -0,0 +1,27 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Called after each word is placed with the word's index, its text, and the number of words
+/// being placed. The index starts over when the words are placed again, like when fitting all
+/// words
+pub type ProgressCallback = Box<dyn Fn(usize, &str, usize) + Send + Sync>;
+
+/// A handle to stop generating a word cloud, keeping the words that were placed so far
+///
+/// Clones share the same state, so one can be kept to cancel from another thread
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,100 @@
+use image::GrayImage;
+use std::time::Duration;
+
+use crate::WordMetadata;
+
+/// Why a word was left out of a word cloud
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DropReason {
+    /// The word would have been smaller than the minimum font size
+    BelowMinFontSize,
+    /// There was no space left for the word, even at the minimum font size
+    NoSpace,
+    /// Placement stopped at a more frequent word before this one was tried
+    NotAttempted,
+    /// Generation was cancelled before the word was placed
+    Cancelled,
+    /// The pinned word reaches outside of the word cloud
+    OutOfBounds,
+    /// The pinned word covers a keep-out zone, the outside of the mask, or another pinned word
+    Obstructed,
+}
+
+#[derive(Clone, Debug)]
+pub struct DroppedWord {
+    pub text: String,
+    pub reason: DropReason,
+}
+
+#[derive(Clone, Debug)]
+pub struct PlacedWordStats {
+    pub text: String,
+    /// The center of the word in pixels, before the word cloud is scaled
+    pub center: (f32, f32),
+    /// The rotation of the word in degrees, counter-clockwise
+    pub rotation: f32,
+    /// The font size the word was given before looking for space for it
+    pub initial_font_size: f32,
+    pub font_size: f32,
+    pub metadata: Option<WordMetadata>,
+}
+
+impl PlacedWordStats {
+    /// How much the font size was reduced to find space for the word
+    pub fn font_size_reduction(&self) -> f32 {
+        self.initial_font_size - self.font_size
+    }
+}
+
+/// Information about how the words of a word cloud were placed
+#[derive(Debug, Default)]
+pub struct GenerationReport {
+    /// The words that were placed, from most to least frequent, after any pinned words
+    pub placed_words: Vec<PlacedWordStats>,
+    /// The words that couldn't fit in the word cloud, from most to least frequent, after any
+    /// pinned words
+    pub dropped_words: Vec<DroppedWord>,
+    /// Whether generation was cancelled before every word could be placed
+    pub cancelled: bool,
+    /// The percentage of the available space (the mask, if there is one) covered by words
+    pub coverage: f32,
+    /// How long it took to generate the word cloud
+    pub duration: Duration,
+}
+
+/// Returns the percentage of the free pixels in `before` that are taken in `after`
+pub(crate) fn coverage(before: &GrayImage, after: &GrayImage) -> f32 {
+    let (free, taken) = before
+        .as_raw()
+        .iter()
+        .zip(after.as_raw())
+        .filter(|(before, _)| **before == 0)
+        .fold((0, 0), |(free, taken), (_, after)| (free + 1, taken + (*after != 0) as usize));
+
+    if free == 0 {
+        0.0
+    } else {
+        taken as f32 / free as f32 * 100.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+
+    #[test]
+    fn coverage_ignores_masked_pixels() {
+        let mut before = GrayImage::from_pixel(4, 4, Luma([0]));
+        for x in 0..4 {
+            before.put_pixel(x, 0, Luma([255]));
+        }
+
+        let mut after = before.clone();
+        for x in 0..3 {
+            after.put_pixel(x, 1, Luma([1]));
+        }
+
+        assert_eq!(coverage(&before, &after), 25.0);
+    }
+}
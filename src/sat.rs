@@ -2,6 +2,8 @@
 // https://en.wikipedia.org/wiki/Summed-area_table
 use nanorand::{Rng, WyRand};
 
+use crate::progress::CancellationToken;
+
 #[cfg(feature = "visualize")]
 use crate::visualize::{CheckRect, Message};
 #[cfg(feature = "visualize")]
@@ -44,23 +46,92 @@ pub fn region_is_empty(
     tl as i32 + br as i32 - tr as i32 - bl as i32 == 0
 }
 
+/// Picks one of the empty positions a search comes across at random
+pub struct PositionPicker<'a> {
+    rng: &'a mut WyRand,
+    weight: Option<Box<dyn Fn(u32, u32) -> f32 + 'a>>,
+    available_points: u32,
+    total_weight: f32,
+    point: Option<Point>,
+}
+
+impl<'a> PositionPicker<'a> {
+    pub fn new(rng: &'a mut WyRand) -> Self {
+        Self { rng, weight: None, available_points: 0, total_weight: 0.0, point: None }
+    }
+
+    /// Makes positions more likely to be picked the larger their weight is. Positions with a
+    /// weight of zero are never picked
+    pub fn with_weight(mut self, weight: impl Fn(u32, u32) -> f32 + 'a) -> Self {
+        self.weight = Some(Box::new(weight));
+        self
+    }
+
+    fn offer(&mut self, x: u32, y: u32) {
+        match &self.weight {
+            None => {
+                // https://en.wikipedia.org/wiki/Reservoir_sampling
+                let random_num = self.rng.generate_range(0..=self.available_points);
+                if random_num == self.available_points {
+                    self.point = Some(Point { x, y });
+                }
+                self.available_points += 1;
+            }
+            Some(weight) => {
+                // Weighted reservoir sampling: each position ends up picked with a probability
+                // of its weight over the total weight
+                let weight = weight(x, y);
+                if weight <= 0.0 {
+                    return;
+                }
+
+                self.total_weight += weight;
+                if self.rng.generate::<f32>() * self.total_weight < weight {
+                    self.point = Some(Point { x, y });
+                }
+            }
+        }
+    }
+}
+
 pub fn find_space_for_rect(
     table: &[u32],
     table_width: u32,
     table_height: u32,
     rect: &Rect,
     rng: &mut WyRand,
+) -> Option<Point> {
+    find_space_for_rect_cancellable(
+        table,
+        table_width,
+        table_height,
+        rect,
+        PositionPicker::new(rng),
+        &CancellationToken::new(),
+    )
+}
+
+/// Finds space for a rect like [`find_space_for_rect`], picking between the empty positions
+/// with `picker` and giving up once the token is cancelled
+pub fn find_space_for_rect_cancellable(
+    table: &[u32],
+    table_width: u32,
+    table_height: u32,
+    rect: &Rect,
+    mut picker: PositionPicker,
+    cancellation_token: &CancellationToken,
 ) -> Option<Point> {
     let max_x = table_width - rect.width;
     let max_y = table_height - rect.height;
 
-    let mut available_points = 0;
-    let mut random_point = None;
-
     #[cfg(feature = "visualize")]
     let mut visualize_buf = BufWriter::new(stdout());
 
     for y in 0..max_y {
+        if cancellation_token.is_cancelled() {
+            return None;
+        }
+
         for x in 0..max_x {
             let empty = region_is_empty(
                 table,
@@ -80,17 +151,12 @@ pub fn find_space_for_rect(
             };
 
             if empty {
-                // https://en.wikipedia.org/wiki/Reservoir_sampling
-                let random_num = rng.generate_range(0..=available_points);
-                if random_num == available_points {
-                    random_point = Some(Point { x, y });
-                }
-                available_points += 1;
+                picker.offer(x, y);
             }
         }
     }
 
-    random_point
+    picker.point
 }
 
 pub fn find_space_for_rect_masked(
@@ -100,17 +166,40 @@ pub fn find_space_for_rect_masked(
     skip_list: &[(usize, usize)],
     rect: &Rect,
     rng: &mut WyRand,
+) -> Option<Point> {
+    find_space_for_rect_masked_cancellable(
+        table,
+        table_width,
+        table_height,
+        skip_list,
+        rect,
+        PositionPicker::new(rng),
+        &CancellationToken::new(),
+    )
+}
+
+/// Finds space for a rect like [`find_space_for_rect_masked`], picking between the empty
+/// positions with `picker` and giving up once the token is cancelled
+pub fn find_space_for_rect_masked_cancellable(
+    table: &[u32],
+    table_width: u32,
+    table_height: u32,
+    skip_list: &[(usize, usize)],
+    rect: &Rect,
+    mut picker: PositionPicker,
+    cancellation_token: &CancellationToken,
 ) -> Option<Point> {
     let max_x = table_width - rect.width;
     let max_y = table_height - rect.height;
 
-    let mut available_points = 0;
-    let mut random_point = None;
-
     #[cfg(feature = "visualize")]
     let mut visualize_buf = BufWriter::new(stdout());
 
     for y in 0..max_y {
+        if cancellation_token.is_cancelled() {
+            return None;
+        }
+
         let (furthest_right, furthest_left) = skip_list[y as usize];
         for x in furthest_right..furthest_left.min(max_x as usize) {
             let empty = region_is_empty(
@@ -134,20 +223,52 @@ pub fn find_space_for_rect_masked(
             };
 
             if empty {
-                // https://en.wikipedia.org/wiki/Reservoir_sampling
-                let random_num: u32 = rng.generate_range(0..=available_points);
-                if random_num == available_points {
-                    random_point = Some(Point { x: x as u32, y });
-                }
-                available_points += 1;
+                picker.offer(x as u32, y);
             }
         }
     }
 
-    // println!("stopped_at: {} / {}", stopped_at, available_points);
-    // println!("{}", available_points);
+    picker.point
+}
+
+/// Finds space for a rect like [`find_space_for_rect_cancellable`], where only the regions of
+/// the rect need to be empty. The regions are relative to the rect's top left corner
+pub fn find_space_for_regions(
+    table: &[u32],
+    table_width: u32,
+    table_height: u32,
+    rect: &Rect,
+    regions: &[Region],
+    mut picker: PositionPicker,
+    cancellation_token: &CancellationToken,
+) -> Option<Point> {
+    let max_x = table_width - rect.width;
+    let max_y = table_height - rect.height;
+
+    for y in 0..max_y {
+        if cancellation_token.is_cancelled() {
+            return None;
+        }
+
+        for x in 0..max_x {
+            let empty = regions.iter().all(|region| {
+                region_is_empty(
+                    table,
+                    table_width as usize,
+                    (x + region.x) as usize,
+                    (y + region.y) as usize,
+                    region.width as usize,
+                    region.height as usize,
+                )
+            });
 
-    random_point
+            if empty {
+                picker.offer(x, y);
+            }
+        }
+    }
+
+    picker.point
 }
 
 pub fn to_summed_area_table(table: &mut [u32], width: usize, start_row: usize) {
@@ -186,6 +307,25 @@ mod tests {
         let expected = [1, 3, 6, 10, 6, 14, 24, 36, 15, 33, 143, 355, 315, 733, 1343, 2155];
         assert_eq!(table, expected);
     }
+
+    #[test]
+    fn weighted_picker_skips_zero_weights() {
+        let table = [0; 100];
+        let mut rng = WyRand::new_seed(0);
+
+        let rect = Rect { width: 4, height: 4 };
+        let token = CancellationToken::new();
+
+        for _ in 0..10 {
+            let picker =
+                PositionPicker::new(&mut rng)
+                    .with_weight(|x, y| if (x, y) == (3, 2) { 1.0 } else { 0.0 });
+            let point = find_space_for_rect_cancellable(&table, 10, 10, &rect, picker, &token)
+                .expect("No space found in an empty table");
+            assert_eq!((point.x, point.y), (3, 2));
+        }
+    }
+
     #[test]
     fn uneven_sat() {
         let mut table = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 100, 200, 300, 400, 500, 600];
@@ -0,0 +1,138 @@
+use std::f32::consts::{FRAC_PI_2, TAU};
+
+use svg::node::element::path::Data;
+
+use crate::vector::{self, VectorMask};
+
+/// A built-in mask shape. Shapes other than ellipses and rectangles keep their aspect ratio
+/// and are centered in the word cloud
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MaskShape {
+    Circle,
+    /// An ellipse filling the word cloud
+    Ellipse,
+    /// A rectangle filling the word cloud, with corners rounded by `radius` times its shorter
+    /// side (0.0 - 0.5)
+    RoundedRect {
+        radius: f32,
+    },
+    Heart,
+    /// A star with `points` points, whose inner corners are `inner_radius` times as far from
+    /// the center as its points (0.0 - 1.0)
+    Star {
+        points: u32,
+        inner_radius: f32,
+    },
+    /// A regular polygon with a corner at the top
+    Polygon {
+        sides: u32,
+    },
+}
+
+impl MaskShape {
+    /// Creates a vector mask of the shape for a word cloud of `width` by `height`
+    pub fn to_vector_mask(self, width: u32, height: u32) -> VectorMask {
+        let (width, height) = (width as f32, height as f32);
+
+        match self {
+            MaskShape::Circle => VectorMask::from_data(
+                vector::ellipse(0.0, 0.0, 1.0, 1.0),
+                Some((-1.0, -1.0, 2.0, 2.0)),
+            ),
+            MaskShape::Ellipse => VectorMask::from_data(
+                vector::ellipse(width / 2.0, height / 2.0, width / 2.0, height / 2.0),
+                Some((0.0, 0.0, width, height)),
+            ),
+            MaskShape::RoundedRect { radius } => {
+                assert!(
+                    (0.0..=0.5).contains(&radius),
+                    "The corner radius must be between 0.0 and 0.5"
+                );
+                let r = radius * width.min(height);
+
+                let data = Data::new()
+                    .move_to((r, 0.0))
+                    .horizontal_line_to(width - r)
+                    .elliptical_arc_to((r, r, 0, 0, 1, width, r))
+                    .vertical_line_to(height - r)
+                    .elliptical_arc_to((r, r, 0, 0, 1, width - r, height))
+                    .horizontal_line_to(r)
+                    .elliptical_arc_to((r, r, 0, 0, 1, 0, height - r))
+                    .vertical_line_to(r)
+                    .elliptical_arc_to((r, r, 0, 0, 1, r, 0))
+                    .close();
+                VectorMask::from_data(data, Some((0.0, 0.0, width, height)))
+            }
+            MaskShape::Heart => {
+                let data = Data::new()
+                    .move_to((50, 88))
+                    .cubic_curve_to((20, 65, 0, 45, 0, 25))
+                    .elliptical_arc_to((25, 25, 0, 0, 1, 50, 15))
+                    .elliptical_arc_to((25, 25, 0, 0, 1, 100, 25))
+                    .cubic_curve_to((100, 45, 80, 65, 50, 88))
+                    .close();
+                VectorMask::from_data(data, None)
+            }
+            MaskShape::Star { points, inner_radius } => {
+                assert!(points >= 2, "A star needs at least 2 points");
+                assert!(
+                    inner_radius > 0.0 && inner_radius <= 1.0,
+                    "The inner radius of a star must be between 0.0 and 1.0"
+                );
+                let corners = (0..points * 2).map(|i| {
+                    let radius = if i % 2 == 0 { 1.0 } else { inner_radius };
+                    (radius, i as f32 / (points * 2) as f32)
+                });
+                VectorMask::from_data(polygon(corners), None)
+            }
+            MaskShape::Polygon { sides } => {
+                assert!(sides >= 3, "A polygon needs at least 3 sides");
+                let corners = (0..sides).map(|i| (1.0, i as f32 / sides as f32));
+                VectorMask::from_data(polygon(corners), None)
+            }
+        }
+    }
+}
+
+/// Joins corners given as a distance from the center and a fraction of a turn clockwise from
+/// the top
+fn polygon(corners: impl Iterator<Item = (f32, f32)>) -> Data {
+    let mut data = Data::new();
+
+    for (i, (radius, turn)) in corners.enumerate() {
+        let angle = turn * TAU - FRAC_PI_2;
+        let corner = (radius * angle.cos(), radius * angle.sin());
+        data = if i == 0 { data.move_to(corner) } else { data.line_to(corner) };
+    }
+
+    data.close()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_inside(shape: MaskShape, x: u32, y: u32) -> bool {
+        shape.to_vector_mask(100, 50).rasterize(100, 50).get_pixel(x, y).0[0] == 0
+    }
+
+    #[test]
+    fn shapes_fill_the_size() {
+        // Circles keep their aspect ratio, while ellipses stretch
+        assert!(is_inside(MaskShape::Circle, 50, 25));
+        assert!(!is_inside(MaskShape::Circle, 20, 25));
+        assert!(is_inside(MaskShape::Ellipse, 5, 25));
+
+        let rounded_rect = MaskShape::RoundedRect { radius: 0.4 };
+        assert!(is_inside(rounded_rect, 50, 1));
+        assert!(!is_inside(rounded_rect, 1, 1));
+
+        let star = MaskShape::Star { points: 5, inner_radius: 0.4 };
+        assert!(is_inside(star, 50, 2));
+        assert!(!is_inside(star, 40, 5));
+
+        assert!(is_inside(MaskShape::Heart, 50, 45));
+        assert!(!is_inside(MaskShape::Heart, 50, 2));
+        assert!(is_inside(MaskShape::Polygon { sides: 6 }, 50, 25));
+    }
+}
@@ -0,0 +1,142 @@
+use ab_glyph::{point, Font, FontVec, Glyph, GlyphId, InvalidFont, Point, PxScale, ScaleFont};
+use rustybuzz::{Direction, Face, UnicodeBuffer};
+use self_cell::self_cell;
+use unicode_bidi::BidiInfo;
+use unicode_segmentation::UnicodeSegmentation;
+
+self_cell!(
+    /// The OpenType tables of a font, parsed once when the font is set rather than for every
+    /// line of text
+    pub struct ShapingFace {
+        owner: Vec<u8>,
+
+        #[covariant]
+        dependent: Face,
+    }
+);
+
+impl ShapingFace {
+    pub fn from_font(font: &FontVec) -> Result<Self, InvalidFont> {
+        ShapingFace::try_new(font.as_slice().to_vec(), |data| {
+            Face::from_slice(data, 0).ok_or(InvalidFont)
+        })
+    }
+}
+
+/// Shapes a line of text with the font's OpenType tables, applying its ligatures, kerning and
+/// contextual forms. Runs of right-to-left text are reordered with the Unicode bidirectional
+/// algorithm, so the glyphs are laid out left to right from the pen `position` in the order they
+/// are displayed. Returns the glyphs and the advance width of the line
+pub fn shape_line(
+    font: &FontVec,
+    face: &ShapingFace,
+    scale: PxScale,
+    position: Point,
+    text: &str,
+) -> (Vec<Glyph>, f32) {
+    let mut glyphs = Vec::new();
+    if text.is_empty() {
+        return (glyphs, 0.0);
+    }
+
+    let face = face.borrow_dependent();
+    let scaled_font = font.as_scaled(scale);
+    let (h_scale, v_scale) = (scaled_font.h_scale_factor(), scaled_font.v_scale_factor());
+
+    let bidi = BidiInfo::new(text, None);
+    let mut caret = position.x;
+
+    for paragraph in &bidi.paragraphs {
+        let (levels, runs) = bidi.visual_runs(paragraph, paragraph.range.clone());
+
+        for run in runs {
+            let mut buffer = UnicodeBuffer::new();
+            buffer.push_str(&text[run.clone()]);
+            buffer.set_direction(match levels[run.start].is_rtl() {
+                true => Direction::RightToLeft,
+                false => Direction::LeftToRight,
+            });
+            buffer.guess_segment_properties();
+
+            // Right-to-left runs come out of the shaper in display order already
+            let shaped = rustybuzz::shape(face, &[], buffer);
+            for (info, position_offset) in shaped.glyph_infos().iter().zip(shaped.glyph_positions())
+            {
+                glyphs.push(Glyph {
+                    id: GlyphId(info.glyph_id as u16),
+                    scale,
+                    // Font units point up, but glyph positions point down
+                    position: point(
+                        caret + position_offset.x_offset as f32 * h_scale,
+                        position.y - position_offset.y_offset as f32 * v_scale,
+                    ),
+                });
+                caret += position_offset.x_advance as f32 * h_scale;
+            }
+        }
+    }
+
+    (glyphs, caret - position.x)
+}
+
+/// Splits the text into grapheme clusters, the characters a reader sees, so combining marks and
+/// emoji sequences stay together
+pub fn graphemes(text: &str) -> Vec<&str> {
+    text.graphemes(true).collect()
+}
+
+// These only run with `cargo test --features shaping`
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn font() -> FontVec {
+        FontVec::try_from_vec(include_bytes!("../fonts/Ubuntu-R.ttf").to_vec()).unwrap()
+    }
+
+    #[test]
+    fn ligatures_are_applied() {
+        let font = font();
+        let face = ShapingFace::from_font(&font).unwrap();
+        let scale = PxScale::from(40.0);
+
+        let (glyphs, width) = shape_line(&font, &face, scale, point(0.0, 0.0), "fi");
+        assert_eq!(glyphs.len(), 1);
+        assert_ne!(glyphs[0].id, font.glyph_id('f'));
+        assert!(width > 0.0);
+    }
+
+    #[test]
+    fn right_to_left_runs_are_reordered() {
+        let font = font();
+        let face = ShapingFace::from_font(&font).unwrap();
+        let scale = PxScale::from(40.0);
+
+        // The paragraph starts with Hebrew, so it reads right to left, and the Latin run that
+        // comes last in the text is displayed first
+        let (glyphs, _) =
+            shape_line(&font, &face, scale, point(10.0, 30.0), "\u{5e9}\u{5dc}\u{5d5}\u{5dd} ab");
+        assert_eq!(glyphs.len(), 7);
+        assert_eq!(glyphs[0].id, font.glyph_id('a'));
+        assert_eq!(glyphs[1].id, font.glyph_id('b'));
+        assert_eq!(glyphs[0].position, point(10.0, 30.0));
+        assert!(glyphs.windows(2).all(|g| g[0].position.x <= g[1].position.x));
+    }
+
+    #[test]
+    fn vertical_text_keeps_graphemes_together() {
+        let font = font();
+        let face = ShapingFace::from_font(&font).unwrap();
+        let scale = PxScale::from(40.0);
+
+        // An e with a combining acute accent, then an x
+        let glyphs = crate::text::text_to_glyphs(
+            "e\u{301}x",
+            &font,
+            Some(&face),
+            scale,
+            crate::WritingMode::Vertical,
+        );
+        assert_eq!(glyphs.height, (font.as_scaled(scale).height() * 2.0).ceil() as u32);
+    }
+}
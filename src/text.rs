@@ -1,5 +1,26 @@
+#[cfg(not(feature = "shaping"))]
+use ab_glyph::InvalidFont;
 use ab_glyph::{point, Font, FontVec, Glyph, Point, PxScale, ScaleFont};
-use image::{GrayImage, Luma, Pixel, Rgba, RgbaImage};
+use image::{GrayImage, Luma, Rgba, RgbaImage};
+
+use crate::effects::{self, Coverage, TextEffects};
+use crate::sat::Region;
+use crate::{color, WritingMode};
+
+#[cfg(feature = "shaping")]
+pub use crate::shaping::ShapingFace;
+
+/// Stands in for a font's OpenType tables without the `shaping` feature, where text is laid out
+/// a character at a time
+#[cfg(not(feature = "shaping"))]
+pub struct ShapingFace;
+
+#[cfg(not(feature = "shaping"))]
+impl ShapingFace {
+    pub fn from_font(_font: &FontVec) -> Result<Self, InvalidFont> {
+        Ok(ShapingFace)
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct GlyphData {
@@ -8,135 +29,597 @@ pub struct GlyphData {
     pub height: u32,
 }
 
-pub fn text_to_glyphs(text: &str, font: &FontVec, scale: PxScale) -> GlyphData {
+pub fn text_to_glyphs(
+    text: &str,
+    font: &FontVec,
+    face: Option<&ShapingFace>,
+    scale: PxScale,
+    writing_mode: WritingMode,
+) -> GlyphData {
     let scaled_font = font.as_scaled(scale);
 
-    let mut glyphs = Vec::new();
-    layout_paragraph(scaled_font, point(0.0, 0.0), 9999.0, text, &mut glyphs);
+    let (glyphs, glyphs_width, glyphs_height) = match writing_mode {
+        WritingMode::Horizontal => {
+            // Line breaks start a new line below the last one
+            let lines: Vec<_> =
+                text.lines().map(|line| line.replace(char::is_control, "")).collect();
+            let v_advance = scaled_font.height() + scaled_font.line_gap();
 
-    let glyphs_height = scaled_font.height().ceil() as u32;
-    let glyphs_width = {
-        let min_x = glyphs.first().unwrap().position.x;
-        let last_glyph = glyphs.last().unwrap();
-        let max_x = last_glyph.position.x + scaled_font.h_advance(last_glyph.id);
-        (max_x - min_x).ceil() as u32
+            let mut glyphs = Vec::new();
+            let mut width = 0.0;
+            for (i, line) in lines.iter().enumerate() {
+                let position = point(0.0, scaled_font.ascent() + v_advance * i as f32);
+                let (line_glyphs, line_width) = layout_line(font, face, scale, position, line);
+
+                glyphs.extend(line_glyphs);
+                width = f32::max(width, line_width);
+            }
+
+            let line_count = lines.len().max(1);
+            (glyphs, width, scaled_font.height() + v_advance * (line_count - 1) as f32)
+        }
+        WritingMode::Vertical => {
+            let cells = column_cells(text, font, face, scale);
+            let column_width = cells.iter().map(|cell| cell.2).fold(0.0, f32::max);
+            let glyphs = cells
+                .iter()
+                .flat_map(|(cell, position, _)| layout_line(font, face, scale, *position, cell).0)
+                .collect();
+
+            (glyphs, column_width, scaled_font.height() * cells.len() as f32)
+        }
+    };
+
+    // Text made up of only control characters has nothing to draw
+    if glyphs.is_empty() {
+        return GlyphData { glyphs, width: 0, height: 0 };
+    }
+
+    GlyphData { glyphs, width: glyphs_width.ceil() as u32, height: glyphs_height.ceil() as u32 }
+}
+
+/// Returns the size of the bounding box of a `width` by `height` rectangle rotated by `angle`
+/// degrees
+pub fn rotated_size(width: u32, height: u32, angle: f32) -> (u32, u32) {
+    let (sin, cos) = angle.to_radians().sin_cos();
+    let (sin, cos) = (sin.abs(), cos.abs());
+
+    // Avoid growing the box by a pixel because of float error on right angles
+    let rotated_width = (width as f32 * cos + height as f32 * sin - 1e-3).ceil().max(0.0);
+    let rotated_height = (width as f32 * sin + height as f32 * cos - 1e-3).ceil().max(0.0);
+
+    (rotated_width as u32, rotated_height as u32)
+}
+
+/// Rasterizes the glyphs into a coverage buffer the size of the text. Parts of glyphs that reach
+/// past the text's box, like the descender of a `j` with a negative side bearing, are clipped
+fn rasterize_glyphs(glyph_data: &GlyphData, font: &FontVec) -> Vec<f32> {
+    let width = glyph_data.width as i64;
+    let height = glyph_data.height as i64;
+    let mut coverage = vec![0.0; (width * height) as usize];
+
+    for glyph in &glyph_data.glyphs {
+        if let Some(outlined) = font.outline_glyph(glyph.clone()) {
+            let bounds = outlined.px_bounds();
+            // The bounds can be negative, so offset them before converting to pixels
+            let (min_x, min_y) = (bounds.min.x.floor() as i64, bounds.min.y.floor() as i64);
+
+            outlined.draw(|x, y, v| {
+                let x = min_x + x as i64;
+                let y = min_y + y as i64;
+
+                if (0..width).contains(&x) && (0..height).contains(&y) {
+                    let px = &mut coverage[(y * width + x) as usize];
+                    *px = *px + v - *px * v;
+                }
+            });
+        }
+    }
+
+    coverage
+}
+
+fn sample_coverage(coverage: &[f32], width: u32, height: u32, x: f32, y: f32) -> f32 {
+    let coverage_at = |x: f32, y: f32| {
+        if x < 0.0 || y < 0.0 || x >= width as f32 || y >= height as f32 {
+            0.0
+        } else {
+            coverage[y as usize * width as usize + x as usize]
+        }
     };
 
-    GlyphData { glyphs, width: glyphs_width, height: glyphs_height }
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+
+    let top = coverage_at(x0, y0) * (1.0 - fx) + coverage_at(x0 + 1.0, y0) * fx;
+    let bottom = coverage_at(x0, y0 + 1.0) * (1.0 - fx) + coverage_at(x0 + 1.0, y0 + 1.0) * fx;
+
+    top * (1.0 - fy) + bottom * fy
+}
+
+/// Where a word is drawn: the point `pivot` of the unrotated text is moved to `center` in the
+/// buffer, and the text is rotated `angle` degrees counter-clockwise around it. Both points can
+/// fall between pixels, so scaled words land exactly where the scaled layout put them
+#[derive(Clone, Copy, Debug)]
+pub struct WordTransform {
+    pub center: Point,
+    pub pivot: Point,
+    pub angle: f32,
+}
+
+impl WordTransform {
+    /// Rotates the text around its center, within the bounding box whose top left corner is
+    /// `point`
+    pub fn at(glyph_data: &GlyphData, point: Point, angle: f32) -> Self {
+        let (width, height) = (glyph_data.width as f32, glyph_data.height as f32);
+        let (rotated_width, rotated_height) =
+            rotated_size(glyph_data.width, glyph_data.height, angle);
+
+        WordTransform {
+            center: ab_glyph::point(
+                point.x + rotated_width as f32 / 2.0,
+                point.y + rotated_height as f32 / 2.0,
+            ),
+            pivot: ab_glyph::point(width / 2.0, height / 2.0),
+            angle,
+        }
+    }
+
+    pub fn scaled(&self, scale: f32) -> Self {
+        WordTransform {
+            center: ab_glyph::point(self.center.x * scale, self.center.y * scale),
+            pivot: ab_glyph::point(self.pivot.x * scale, self.pivot.y * scale),
+            ..*self
+        }
+    }
+
+    /// The pixels the rotated text can cover, as the top left corner and the exclusive bottom
+    /// right corner
+    fn bounds(&self, glyph_data: &GlyphData) -> ((i64, i64), (i64, i64)) {
+        let (width, height) = (glyph_data.width as f32, glyph_data.height as f32);
+        let (sin, cos) = self.angle.to_radians().sin_cos();
+
+        let corners = [(0.0, 0.0), (width, 0.0), (0.0, height), (width, height)].map(|(x, y)| {
+            let (x, y) = (x - self.pivot.x, y - self.pivot.y);
+            (self.center.x + x * cos + y * sin, self.center.y - x * sin + y * cos)
+        });
+
+        // Avoid growing the box by a pixel because of float error on whole pixels
+        let min = |axis: fn(&(f32, f32)) -> f32| {
+            (corners.iter().map(axis).fold(f32::MAX, f32::min) + 1e-3).floor() as i64
+        };
+        let max = |axis: fn(&(f32, f32)) -> f32| {
+            (corners.iter().map(axis).fold(f32::MIN, f32::max) - 1e-3).ceil() as i64
+        };
+
+        ((min(|c| c.0), min(|c| c.1)), (max(|c| c.0), max(|c| c.1)))
+    }
+}
+
+/// Calls `draw` with the coordinates and coverage of every pixel of the transformed glyphs
+/// that falls within a buffer of the given size
+fn draw_rotated_glyphs(
+    glyph_data: &GlyphData,
+    font: &FontVec,
+    transform: WordTransform,
+    (buffer_width, buffer_height): (u32, u32),
+    mut draw: impl FnMut(u32, u32, f32),
+) {
+    let coverage = rasterize_glyphs(glyph_data, font);
+
+    let (width, height) = (glyph_data.width, glyph_data.height);
+    let (sin, cos) = transform.angle.to_radians().sin_cos();
+
+    let ((min_x, min_y), (max_x, max_y)) = transform.bounds(glyph_data);
+    let (min_x, min_y) = (min_x.max(0), min_y.max(0));
+    let (max_x, max_y) = (max_x.min(buffer_width as i64), max_y.min(buffer_height as i64));
+
+    for y in min_y..max_y {
+        for x in min_x..max_x {
+            // Map the center of the pixel back onto the unrotated text
+            let dx = x as f32 + 0.5 - transform.center.x;
+            let dy = y as f32 + 0.5 - transform.center.y;
+
+            let text_x = dx * cos - dy * sin + transform.pivot.x;
+            let text_y = dx * sin + dy * cos + transform.pivot.y;
+
+            let v = sample_coverage(&coverage, width, height, text_x - 0.5, text_y - 0.5);
+
+            if v > 0.0 {
+                draw(x as u32, y as u32, v);
+            }
+        }
+    }
+}
+
+/// The number of bands of rows a rotated word's footprint is split into
+const FOOTPRINT_BANDS: u32 = 16;
+
+/// Returns the parts of its bounding box a word rotated to a diagonal angle covers, as bands of
+/// rows relative to the box's top left corner. The box and the bands include `spacing`, which
+/// is split evenly around the word like it is when placing it
+pub fn rotated_footprint(
+    glyph_data: &GlyphData,
+    font: &FontVec,
+    angle: f32,
+    spacing: u32,
+) -> Vec<Region> {
+    let (width, height) = rotated_size(glyph_data.width, glyph_data.height, angle);
+    let (width, height) = (width + spacing, height + spacing);
+
+    let half_spacing = spacing as f32 / 2.0;
+    let transform = WordTransform::at(glyph_data, point(half_spacing, half_spacing), angle);
+
+    // The leftmost and rightmost pixel the word covers on every row
+    let mut spans: Vec<Option<(u32, u32)>> = vec![None; height as usize];
+    draw_rotated_glyphs(glyph_data, font, transform, (width, height), |x, y, v| {
+        if v >= 0.05 {
+            let span = &mut spans[y as usize];
+            *span = Some(span.map_or((x, x), |(min, max)| (min.min(x), max.max(x))));
+        }
+    });
+
+    // Grow the spans by the spacing, sideways and onto the rows around them
+    let reach = half_spacing.ceil() as usize;
+    let spans: Vec<_> = (0..spans.len())
+        .map(|y| {
+            spans[y.saturating_sub(reach)..(y + reach + 1).min(spans.len())]
+                .iter()
+                .flatten()
+                .fold(None, |span: Option<(u32, u32)>, &(min, max)| {
+                    Some(span.map_or((min, max), |span| (span.0.min(min), span.1.max(max))))
+                })
+                .map(|(min, max)| {
+                    (min.saturating_sub(reach as u32), (max + reach as u32).min(width - 1))
+                })
+        })
+        .collect();
+
+    let band_height = height.div_ceil(FOOTPRINT_BANDS).max(1);
+    spans
+        .chunks(band_height as usize)
+        .enumerate()
+        .filter_map(|(band, rows)| {
+            let (min, max) = rows
+                .iter()
+                .flatten()
+                .fold((u32::MAX, 0), |(min, max), span| (min.min(span.0), max.max(span.1)));
+
+            (min <= max).then(|| Region {
+                x: min,
+                y: band as u32 * band_height,
+                width: max - min + 1,
+                height: rows.len() as u32,
+            })
+        })
+        .collect()
 }
 
+/// Draws the word with its effects, clipping anything that falls outside of the buffer
 pub fn draw_glyphs_to_rgba_buffer(
     buffer: &mut RgbaImage,
     glyph_data: GlyphData,
     font: &FontVec,
-    point: Point,
-    rotate: bool,
+    transform: WordTransform,
     pixel: Rgba<u8>,
+    effects: &TextEffects,
 ) {
-    let width = glyph_data.width;
+    // Leave room around the word for the effects to spread into
+    let padding = effects.extent() as i64;
+    let ((min_x, min_y), (max_x, max_y)) = transform.bounds(&glyph_data);
 
-    for glyph in glyph_data.glyphs {
-        if let Some(outlined) = font.outline_glyph(glyph) {
-            let bounds = outlined.px_bounds();
+    // Only draw the part of the word close enough to the buffer for it or its effects to show
+    let (buffer_width, buffer_height) = (buffer.width() as i64, buffer.height() as i64);
+    let origin = ((min_x - padding).max(-padding * 2), (min_y - padding).max(-padding * 2));
+    let end = (
+        (max_x + padding).min(buffer_width + padding * 2),
+        (max_y + padding).min(buffer_height + padding * 2),
+    );
 
-            outlined.draw(|x, y, v| {
-                let (final_x, final_y) = if !rotate {
-                    (
-                        point.x as u32 + bounds.min.x as u32 + x,
-                        point.y as u32 + bounds.min.y as u32 + y,
-                    )
-                } else {
-                    (
-                        y + point.x as u32 + bounds.min.y as u32,
-                        width + point.y as u32 - bounds.min.x as u32 - x,
-                    )
-                };
-
-                let px = buffer.get_pixel_mut(final_x, final_y);
-
-                px.apply2(&pixel, |old, new| ((v * new as f32) + (1.0 - v) * old as f32) as u8);
-
-                if px != &Rgba::from([0; 4]) {
-                    px.0[3] = 0xFF;
-                }
-            });
+    let mut coverage =
+        Coverage::new((end.0 - origin.0).max(0) as u32, (end.1 - origin.1).max(0) as u32);
+    let layer_transform = WordTransform {
+        center: ab_glyph::point(
+            transform.center.x - origin.0 as f32,
+            transform.center.y - origin.1 as f32,
+        ),
+        ..transform
+    };
+    draw_rotated_glyphs(&glyph_data, font, layer_transform, coverage.dimensions(), |x, y, v| {
+        coverage.put_pixel(x, y, Luma([v]));
+    });
+
+    let outlined = match effects.stroke {
+        Some(stroke) => effects::dilate(&coverage, stroke.width),
+        None => coverage.clone(),
+    };
+
+    for (layer, (dx, dy), color) in effects.background_layers(&outlined) {
+        draw_coverage(buffer, &layer, (origin.0 + dx, origin.1 + dy), color);
+    }
+
+    if let Some(stroke) = effects.stroke {
+        draw_coverage(buffer, &outlined, origin, stroke.color);
+    }
+
+    draw_coverage(buffer, &coverage, origin, pixel);
+}
+
+/// Composites the color onto the buffer with the coverage as its opacity, skipping pixels
+/// outside of the buffer
+fn draw_coverage(buffer: &mut RgbaImage, coverage: &Coverage, origin: (i64, i64), pixel: Rgba<u8>) {
+    for (x, y, v) in coverage.enumerate_pixels() {
+        let (x, y) = (origin.0 + x as i64, origin.1 + y as i64);
+
+        if v.0[0] > 0.0
+            && x >= 0
+            && y >= 0
+            && x < buffer.width() as i64
+            && y < buffer.height() as i64
+        {
+            color::source_over(buffer.get_pixel_mut(x as u32, y as u32), pixel, v.0[0]);
         }
     }
 }
 
+/// Marks the pixels the word covers, where `point` is the top left corner of its bounding box
 pub fn draw_glyphs_to_gray_buffer(
     buffer: &mut GrayImage,
     glyph_data: GlyphData,
     font: &FontVec,
     point: Point,
-    rotate: bool,
+    angle: f32,
 ) {
-    let width = glyph_data.width;
-
-    for glyph in glyph_data.glyphs {
-        if let Some(outlined) = font.outline_glyph(glyph) {
-            let bounds = outlined.px_bounds();
+    let transform = WordTransform::at(&glyph_data, point, angle);
+    let dimensions = buffer.dimensions();
 
-            outlined.draw(|x, y, v| {
-                if v < 0.05 {
-                    return;
-                }
-
-                let (final_x, final_y) = if !rotate {
-                    (
-                        point.x as u32 + bounds.min.x as u32 + x,
-                        point.y as u32 + bounds.min.y as u32 + y,
-                    )
-                } else {
-                    (
-                        y + point.x as u32 + bounds.min.y as u32,
-                        width + point.y as u32 - bounds.min.x as u32 - x,
-                    )
-                };
-
-                let px = buffer.get_pixel_mut(final_x, final_y);
-                *px = Luma([1]);
-            });
+    draw_rotated_glyphs(&glyph_data, font, transform, dimensions, |x, y, v| {
+        if v >= 0.05 {
+            buffer.put_pixel(x, y, Luma([1]));
         }
-    }
+    });
 }
 
-pub fn layout_paragraph<F, SF>(
-    font: SF,
+/// Lays out a line of text from the pen `position`, returning the glyphs and the line's advance
+/// width. With the `shaping` feature and the font's `face`, the text is shaped with its OpenType
+/// tables. Otherwise every character maps to one glyph, adjusted by the font's kerning table
+#[cfg_attr(not(feature = "shaping"), allow(unused_variables))]
+fn layout_line(
+    font: &FontVec,
+    face: Option<&ShapingFace>,
+    scale: PxScale,
     position: Point,
-    _max_width: f32,
     text: &str,
-    target: &mut Vec<Glyph>,
-) where
-    F: Font,
-    SF: ScaleFont<F>,
-{
-    let v_advance = font.height() + font.line_gap();
-    let mut caret = position + point(0.0, font.ascent());
-    let mut last_glyph: Option<Glyph> = None;
+) -> (Vec<Glyph>, f32) {
+    #[cfg(feature = "shaping")]
+    if let Some(face) = face {
+        return crate::shaping::shape_line(font, face, scale, position, text);
+    }
+
+    let font = font.as_scaled(scale);
+
+    let mut glyphs: Vec<Glyph> = Vec::new();
+    let mut caret = position;
     for c in text.chars() {
-        if c.is_control() {
-            if c == '\n' {
-                caret = point(position.x, caret.y + v_advance);
-                last_glyph = None;
-            }
-            continue;
-        }
         let mut glyph = font.scaled_glyph(c);
-        if let Some(previous) = last_glyph.take() {
+        if let Some(previous) = glyphs.last() {
             caret.x += font.kern(previous.id, glyph.id);
         }
         glyph.position = caret;
 
-        last_glyph = Some(glyph.clone());
         caret.x += font.h_advance(glyph.id);
 
-        // if !c.is_whitespace() && caret.x > position.x + max_width {
-        //     caret = point(position.x, caret.y + v_advance);
-        //     glyph.position = caret;
-        //     last_glyph = None;
-        // }
+        glyphs.push(glyph);
+    }
+
+    (glyphs, caret.x - position.x)
+}
+
+/// Splits vertical text into the characters stacked top to bottom, each centered on the widest
+/// one. Returns every character's text, pen position and width. With the `shaping` feature, the
+/// characters are grapheme clusters, so combining marks stay with their base
+pub fn column_cells<'t>(
+    text: &'t str,
+    font: &FontVec,
+    face: Option<&ShapingFace>,
+    scale: PxScale,
+) -> Vec<(&'t str, Point, f32)> {
+    #[cfg(feature = "shaping")]
+    let cells = crate::shaping::graphemes(text);
+    #[cfg(not(feature = "shaping"))]
+    let cells: Vec<_> = text.char_indices().map(|(i, c)| &text[i..i + c.len_utf8()]).collect();
+
+    let cells: Vec<_> = cells
+        .into_iter()
+        .filter(|cell| !cell.chars().all(char::is_control))
+        .map(|cell| (cell, layout_line(font, face, scale, point(0.0, 0.0), cell).1))
+        .collect();
+    let column_width = cells.iter().map(|(_, width)| *width).fold(0.0, f32::max);
+
+    let font = font.as_scaled(scale);
+    cells
+        .into_iter()
+        .enumerate()
+        .map(|(i, (cell, width))| {
+            let position =
+                point((column_width - width) / 2.0, font.ascent() + font.height() * i as f32);
+            (cell, position, width)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effects::{Shadow, Stroke};
+    use nanorand::{Rng, WyRand};
+
+    #[test]
+    fn rotated_size_right_angles() {
+        assert_eq!(rotated_size(120, 30, 0.0), (120, 30));
+        assert_eq!(rotated_size(120, 30, 90.0), (30, 120));
+        assert_eq!(rotated_size(120, 30, -90.0), (30, 120));
+        assert_eq!(rotated_size(120, 30, 180.0), (120, 30));
+    }
+
+    #[test]
+    fn rotated_size_diagonal() {
+        assert_eq!(rotated_size(100, 100, 45.0), (142, 142));
+        assert_eq!(rotated_size(100, 0, -45.0), (71, 71));
+    }
+
+    #[test]
+    fn vertical_text_is_stacked() {
+        let font = FontVec::try_from_vec(include_bytes!("../fonts/Ubuntu-B.ttf").to_vec()).unwrap();
+        let scale = PxScale::from(40.0);
+
+        let horizontal = text_to_glyphs("wcloud", &font, None, scale, WritingMode::Horizontal);
+        let vertical = text_to_glyphs("wcloud", &font, None, scale, WritingMode::Vertical);
+
+        assert_eq!(vertical.glyphs.len(), 6);
+        assert_eq!(vertical.height, (font.as_scaled(scale).height() * 6.0).ceil() as u32);
+        assert!(vertical.width < horizontal.width);
+        assert!(vertical.glyphs.windows(2).all(|g| g[0].position.y < g[1].position.y));
+    }
+
+    #[test]
+    fn line_breaks_start_new_lines() {
+        let font = FontVec::try_from_vec(include_bytes!("../fonts/Ubuntu-B.ttf").to_vec()).unwrap();
+        let scale = PxScale::from(40.0);
+
+        let lines = text_to_glyphs("wc\nloud", &font, None, scale, WritingMode::Horizontal);
+        let longest = text_to_glyphs("loud", &font, None, scale, WritingMode::Horizontal);
+
+        let font = font.as_scaled(scale);
+        assert_eq!(lines.glyphs.len(), 6);
+        assert_eq!(lines.width, longest.width);
+        assert_eq!(lines.height, (font.height() * 2.0 + font.line_gap()).ceil() as u32);
+        assert_eq!(lines.glyphs[2].position.x, 0.0);
+        assert!(lines.glyphs[2].position.y > lines.glyphs[1].position.y);
+    }
+
+    #[test]
+    fn drawing_is_clipped_to_the_buffer() {
+        let font = FontVec::try_from_vec(include_bytes!("../fonts/Ubuntu-B.ttf").to_vec()).unwrap();
+        let glyphs =
+            text_to_glyphs("wcloud", &font, None, PxScale::from(40.0), WritingMode::Horizontal);
+
+        // Words hanging off any edge of the buffer are cut off instead of panicking
+        let mut gray = GrayImage::new(50, 20);
+        for (x, y) in [(-30.0, -10.0), (40.0, 5.0), (10.0, 15.0)] {
+            draw_glyphs_to_gray_buffer(&mut gray, glyphs.clone(), &font, point(x, y), 20.0);
+        }
+        assert!(gray.pixels().any(|p| p.0[0] == 1));
+
+        let mut rgba = RgbaImage::new(50, 20);
+        let transform = WordTransform::at(&glyphs, point(-40.5, -50.5), 45.0);
+        let white = Rgba([255, 255, 255, 255]);
+        draw_glyphs_to_rgba_buffer(
+            &mut rgba,
+            glyphs,
+            &font,
+            transform,
+            white,
+            &TextEffects::default(),
+        );
+        assert!(rgba.pixels().any(|p| p.0[3] > 0));
+    }
+
+    /// Draws words of random text, fonts, sizes and rotations hanging off the edges of random
+    /// buffers, none of which should panic
+    #[test]
+    fn words_at_the_edges_never_panic() {
+        let fonts = [
+            include_bytes!("../fonts/Ubuntu-B.ttf").to_vec(),
+            include_bytes!("../fonts/Ubuntu-R.ttf").to_vec(),
+            include_bytes!("../fonts/DroidSansMono.ttf").to_vec(),
+        ]
+        .map(|bytes| FontVec::try_from_vec(bytes).unwrap());
+        // Glyphs with negative side bearings, descenders, accents, ligatures and missing glyphs
+        let alphabet: Vec<_> = "wcloud jfgyQ_ÅÉ漢字ﬁ \n\t".chars().collect();
+        let color = Rgba([255, 0, 0, 128]);
+
+        let mut rng = WyRand::new_seed(49);
+        for _ in 0..500 {
+            let font = &fonts[rng.generate_range(0..fonts.len())];
+            let length = rng.generate_range(0..8usize);
+            let text: String =
+                (0..length).map(|_| alphabet[rng.generate_range(0..alphabet.len())]).collect();
+            let size = 1.0 + rng.generate::<f32>() * 120.0;
+            let angle = rng.generate::<f32>() * 720.0 - 360.0;
+            let writing_mode = match rng.generate_range(0..2u8) {
+                0 => WritingMode::Horizontal,
+                _ => WritingMode::Vertical,
+            };
+            let glyphs = text_to_glyphs(&text, font, None, PxScale::from(size), writing_mode);
+
+            let (width, height) = (rng.generate_range(0..64u32), rng.generate_range(0..64u32));
+            // From fully off the top left of the buffer to fully off its bottom right
+            let reach = glyphs.width.max(glyphs.height) as f32 * 1.5 + 10.0;
+            let position = point(
+                rng.generate::<f32>() * (width as f32 + reach * 2.0) - reach,
+                rng.generate::<f32>() * (height as f32 + reach * 2.0) - reach,
+            );
+
+            let mut gray = GrayImage::new(width, height);
+            draw_glyphs_to_gray_buffer(&mut gray, glyphs.clone(), font, position, angle);
+
+            let effects = TextEffects {
+                stroke: Some(Stroke { width: rng.generate::<f32>() * 4.0, color }),
+                shadow: Some(Shadow { offset: (-3.0, 2.5), blur: 2.0, color }),
+                glow: None,
+            };
+            let transform = WordTransform::at(&glyphs, position, angle);
+            let mut rgba = RgbaImage::new(width, height);
+            draw_glyphs_to_rgba_buffer(&mut rgba, glyphs, font, transform, color, &effects);
+        }
+    }
+
+    #[test]
+    fn words_are_positioned_between_pixels() {
+        let font = FontVec::try_from_vec(include_bytes!("../fonts/Ubuntu-B.ttf").to_vec()).unwrap();
+        let glyphs = text_to_glyphs("l", &font, None, PxScale::from(40.0), WritingMode::Horizontal);
+
+        let draw = |x: f32| {
+            let mut buffer = RgbaImage::new(glyphs.width + 4, glyphs.height);
+            let transform = WordTransform::at(&glyphs, point(x, 0.0), 0.0);
+            let white = Rgba([255, 255, 255, 255]);
+            draw_glyphs_to_rgba_buffer(
+                &mut buffer,
+                glyphs.clone(),
+                &font,
+                transform,
+                white,
+                &TextEffects::default(),
+            );
+            buffer
+        };
+        let center_of_mass = |buffer: &RgbaImage| {
+            let (sum, weight) = buffer.enumerate_pixels().fold((0.0, 0.0), |(sum, weight), p| {
+                let alpha = p.2 .0[3] as f32;
+                (sum + p.0 as f32 * alpha, weight + alpha)
+            });
+            sum / weight
+        };
+
+        let shift = center_of_mass(&draw(1.5)) - center_of_mass(&draw(1.0));
+        assert!((shift - 0.5).abs() < 0.05, "shifted by {}", shift);
+    }
+
+    #[test]
+    fn diagonal_footprint_is_smaller_than_its_box() {
+        let font = FontVec::try_from_vec(include_bytes!("../fonts/Ubuntu-B.ttf").to_vec()).unwrap();
+        let glyphs =
+            text_to_glyphs("wcloud", &font, None, PxScale::from(40.0), WritingMode::Horizontal);
+
+        let (width, height) = rotated_size(glyphs.width, glyphs.height, 45.0);
+        let (width, height) = (width + 4, height + 4);
+        let footprint = rotated_footprint(&glyphs, &font, 45.0, 4);
 
-        target.push(glyph);
+        assert!(footprint
+            .iter()
+            .all(|region| region.x + region.width <= width && region.y + region.height <= height));
+        let area: u32 = footprint.iter().map(|region| region.width * region.height).sum();
+        assert!(area < width * height * 3 / 4, "{} of {}", area, width * height);
     }
 }
@@ -1,6 +1,8 @@
 use regex::{Match, Regex};
 use std::collections::{HashMap, HashSet};
 
+use crate::WeightedWord;
+
 // TODO: Use lazy_static or PHF to make this a HashSet?
 pub const DEFAULT_EXCLUDE_WORDS_TEXT: &str = include_str!("../exclude_words.txt");
 
@@ -84,7 +86,10 @@ impl<'a> Tokenizer {
             .collect()
     }
 
-    fn get_word_frequencies(&'a self, text: &'a str) -> (HashMap<&'a str, usize>, usize) {
+    pub(crate) fn get_word_frequencies(
+        &'a self,
+        text: &'a str,
+    ) -> (HashMap<&'a str, usize>, usize) {
         let mut frequencies = HashMap::new();
 
         let included_words = self.tokenize(text);
@@ -101,20 +106,40 @@ impl<'a> Tokenizer {
     }
 
     pub fn get_normalized_word_frequencies(&'a self, text: &'a str) -> Vec<(&'a str, f32)> {
-        let (frequencies, max_freq) = self.get_word_frequencies(text);
+        let (frequencies, _) = self.get_word_frequencies(text);
+
+        let frequencies: Vec<_> =
+            frequencies.iter().map(|(key, val)| WeightedWord::new(key, *val as f32)).collect();
+
+        self.normalize_frequencies(&frequencies)
+            .into_iter()
+            .map(|word| (word.text, word.frequency))
+            .collect()
+    }
+
+    /// Sorts words by their frequency, scaling it so the most frequent word has a frequency of
+    /// 1.0, and limits or repeats them to the maximum number of words
+    pub fn normalize_frequencies<'w>(
+        &self,
+        frequencies: &[WeightedWord<'w>],
+    ) -> Vec<WeightedWord<'w>> {
+        let max_freq = frequencies.iter().map(|word| word.frequency).fold(0.0, f32::max);
 
-        if frequencies.is_empty() {
+        if max_freq <= 0.0 {
             return Vec::new();
         }
 
-        let mut normalized_freqs: Vec<(&str, f32)> =
-            frequencies.iter().map(|(key, val)| (*key, *val as f32 / max_freq as f32)).collect();
+        let mut normalized_freqs: Vec<WeightedWord> = frequencies
+            .iter()
+            .filter(|word| word.frequency > 0.0)
+            .map(|word| WeightedWord { frequency: word.frequency / max_freq, ..word.clone() })
+            .collect();
 
         normalized_freqs.sort_by(|a, b| {
-            if a.1 != b.1 {
-                (b.1).partial_cmp(&a.1).unwrap()
+            if a.frequency != b.frequency {
+                (b.frequency).partial_cmp(&a.frequency).unwrap()
             } else {
-                (a.0).partial_cmp(b.0).unwrap()
+                (a.text).partial_cmp(b.text).unwrap()
             }
         });
 
@@ -128,14 +153,13 @@ impl<'a> Tokenizer {
 
             let freqs_clone = normalized_freqs.clone();
             let down_weight =
-                normalized_freqs.last().expect("The normalized frequencies vec is empty").1;
+                normalized_freqs.last().expect("The normalized frequencies vec is empty").frequency;
 
             for i in 1..=times_extend {
-                normalized_freqs.extend(
-                    freqs_clone
-                        .iter()
-                        .map(|(word, freq)| (*word, freq * down_weight.powf(i as f32))),
-                )
+                normalized_freqs.extend(freqs_clone.iter().map(|word| WeightedWord {
+                    frequency: word.frequency * down_weight.powf(i as f32),
+                    ..word.clone()
+                }))
             }
         }
 
@@ -0,0 +1,777 @@
+use std::error::Error;
+use std::f32::consts::TAU;
+use std::path::PathBuf;
+use std::{fmt, fs, io};
+
+use image::{GrayImage, Luma};
+use svg::node::element::path::{Command, Data, Position};
+use svg::node::element::tag::Type;
+use svg::node::element::Path;
+use svg::node::Attributes;
+use svg::parser::Event;
+
+use crate::mask::MaskFit;
+
+/// A mask made of filled SVG shapes. Words are placed inside the shapes, which are rasterized
+/// at the word cloud's size and kept as vectors in SVG output
+#[derive(Clone, Debug)]
+pub struct VectorMask {
+    shapes: Vec<Shape>,
+    /// The part of the shapes' coordinate space that is scaled to the word cloud, as x, y,
+    /// width and height
+    view_box: (f32, f32, f32, f32),
+    fit: MaskFit,
+}
+
+/// Why a vector mask couldn't be created
+#[derive(Debug)]
+pub enum VectorMaskError {
+    /// The SVG file couldn't be read
+    Io(io::Error),
+    /// The SVG document is malformed
+    Svg(String),
+    /// The path data of a shape is malformed
+    PathData(String),
+    /// An attribute that should be a list of numbers, like a `viewBox`, isn't
+    Numbers(String),
+    /// A `transform` attribute is malformed
+    Transform(String),
+    /// There are no shapes to place words in
+    NoShapes,
+    /// The shapes or the view box have no area
+    NoArea,
+}
+
+impl fmt::Display for VectorMaskError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VectorMaskError::Io(error) => write!(f, "Unable to read SVG file: {}", error),
+            VectorMaskError::Svg(error) => write!(f, "Invalid SVG file: {}", error),
+            VectorMaskError::PathData(error) => write!(f, "Invalid SVG path data: {}", error),
+            VectorMaskError::Numbers(value) => write!(f, "Invalid numbers \'{}\'", value),
+            VectorMaskError::Transform(value) => write!(f, "Invalid transform \'{}\'", value),
+            VectorMaskError::NoShapes => write!(f, "The mask has no shapes"),
+            VectorMaskError::NoArea => write!(f, "The mask's shapes have no area"),
+        }
+    }
+}
+
+impl Error for VectorMaskError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            VectorMaskError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Shape {
+    data: Data,
+    even_odd: bool,
+    /// The transforms of the shape and the groups it's in
+    matrix: Matrix,
+}
+
+impl Shape {
+    /// Turns the shape into polygons in the mask's coordinates, splitting curves into lines
+    /// about `tolerance` long
+    fn polygons(&self, tolerance: f32) -> Vec<Vec<(f32, f32)>> {
+        flatten(&self.data, tolerance / self.matrix.scale())
+            .into_iter()
+            .map(|polygon| polygon.into_iter().map(|point| self.matrix.apply(point)).collect())
+            .collect()
+    }
+}
+
+/// An SVG transform matrix, as `[a, b, c, d, e, f]`
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Matrix([f32; 6]);
+
+impl Matrix {
+    const IDENTITY: Matrix = Matrix([1.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+
+    fn apply(&self, (x, y): (f32, f32)) -> (f32, f32) {
+        let [a, b, c, d, e, f] = self.0;
+        (a * x + c * y + e, b * x + d * y + f)
+    }
+
+    /// Returns the matrix that applies `other` first, then this one
+    fn then(&self, other: &Matrix) -> Matrix {
+        let [a, b, c, d, e, f] = self.0;
+        let [oa, ob, oc, od, oe, of] = other.0;
+
+        Matrix([
+            a * oa + c * ob,
+            b * oa + d * ob,
+            a * oc + c * od,
+            b * oc + d * od,
+            a * oe + c * of + e,
+            b * oe + d * of + f,
+        ])
+    }
+
+    /// How much the matrix scales lengths, on average
+    fn scale(&self) -> f32 {
+        let [a, b, c, d, ..] = self.0;
+        (a * d - b * c).abs().sqrt().max(1e-6)
+    }
+}
+
+/// Maps the mask's coordinates to the word cloud's
+#[derive(Clone, Copy, Debug)]
+struct Transform {
+    scale_x: f32,
+    scale_y: f32,
+    x: f32,
+    y: f32,
+}
+
+impl Transform {
+    fn apply(&self, (x, y): (f32, f32)) -> (f32, f32) {
+        (x * self.scale_x + self.x, y * self.scale_y + self.y)
+    }
+}
+
+impl VectorMask {
+    /// Creates a mask from SVG path data, like the `d` attribute of a `<path>`, fitted to the
+    /// path's bounds
+    pub fn from_path_data(data: &str) -> Result<Self, VectorMaskError> {
+        let data =
+            Data::parse(data).map_err(|error| VectorMaskError::PathData(error.to_string()))?;
+        Self::from_shapes(vec![Shape { data, even_odd: false, matrix: Matrix::IDENTITY }], None)
+    }
+
+    /// Creates a mask from the `<path>`, `<polygon>`, `<rect>`, `<circle>` and `<ellipse>`
+    /// elements of an SVG document, fitted to its `viewBox`. The elements' transforms and those
+    /// of the groups they're in are applied, and styles are ignored
+    pub fn from_svg(content: &str) -> Result<Self, VectorMaskError> {
+        let mut shapes = Vec::new();
+        let mut view_box = None;
+        // The transform of every element that's open, with the innermost last
+        let mut matrices = vec![Matrix::IDENTITY];
+
+        let parser = svg::read(content).map_err(|error| VectorMaskError::Svg(error.to_string()))?;
+        for event in parser {
+            let (name, kind, attributes) = match event {
+                Event::Tag(_, Type::End, _) => {
+                    if matrices.len() > 1 {
+                        matrices.pop();
+                    }
+                    continue;
+                }
+                Event::Tag(name, kind, attributes) => (name, kind, attributes),
+                Event::Error(error) => return Err(VectorMaskError::Svg(error.to_string())),
+                _ => continue,
+            };
+
+            let parent = matrices.last().unwrap_or(&Matrix::IDENTITY);
+            let matrix = match attributes.get("transform") {
+                Some(transform) => parent.then(&parse_transform(transform)?),
+                None => *parent,
+            };
+            if matches!(kind, Type::Start) {
+                matrices.push(matrix);
+            }
+
+            let number = |name: &str| attributes.get(name).map_or(0.0, |value| parse_length(value));
+
+            let data = match name {
+                "svg" => {
+                    view_box = parse_view_box(&attributes)?;
+                    None
+                }
+                "path" => attributes
+                    .get("d")
+                    .map(|data| Data::parse(data))
+                    .transpose()
+                    .map_err(|error| VectorMaskError::PathData(error.to_string()))?,
+                "polygon" => attributes.get("points").map(|points| polygon(points)).transpose()?,
+                "rect" => Some(rect(number("x"), number("y"), number("width"), number("height"))),
+                "circle" => Some(ellipse(number("cx"), number("cy"), number("r"), number("r"))),
+                "ellipse" => Some(ellipse(number("cx"), number("cy"), number("rx"), number("ry"))),
+                _ => None,
+            };
+
+            if let Some(data) = data {
+                let even_odd =
+                    attributes.get("fill-rule").is_some_and(|rule| rule.trim() == "evenodd");
+                shapes.push(Shape { data, even_odd, matrix });
+            }
+        }
+
+        Self::from_shapes(shapes, view_box)
+    }
+
+    pub fn from_svg_file(path: PathBuf) -> Result<Self, VectorMaskError> {
+        Self::from_svg(&fs::read_to_string(path).map_err(VectorMaskError::Io)?)
+    }
+
+    pub fn with_fit(mut self, value: MaskFit) -> Self {
+        self.fit = value;
+        self
+    }
+
+    /// Creates a mask from path data, fitted to the view box or to the path's bounds. Only used
+    /// for the crate's own shapes, whose areas can't be empty unless the word cloud is
+    pub(crate) fn from_data(data: Data, view_box: Option<(f32, f32, f32, f32)>) -> Self {
+        let shapes = vec![Shape { data, even_odd: false, matrix: Matrix::IDENTITY }];
+        let view_box = view_box.unwrap_or_else(|| bounds(&shapes));
+
+        VectorMask { shapes, view_box, fit: MaskFit::Fit }
+    }
+
+    fn from_shapes(
+        shapes: Vec<Shape>,
+        view_box: Option<(f32, f32, f32, f32)>,
+    ) -> Result<Self, VectorMaskError> {
+        if shapes.is_empty() {
+            return Err(VectorMaskError::NoShapes);
+        }
+
+        let view_box = view_box.unwrap_or_else(|| bounds(&shapes));
+        if !(view_box.2 > 0.0 && view_box.3 > 0.0) {
+            return Err(VectorMaskError::NoArea);
+        }
+
+        Ok(VectorMask { shapes, view_box, fit: MaskFit::Fit })
+    }
+
+    /// The size of the mask's view box, rounded up to whole pixels
+    pub fn size(&self) -> (u32, u32) {
+        (self.view_box.2.ceil() as u32, self.view_box.3.ceil() as u32)
+    }
+
+    fn transform(&self, width: u32, height: u32) -> Transform {
+        let (x, y, view_width, view_height) = self.view_box;
+
+        let mut scale_x = width as f32 / view_width;
+        let mut scale_y = height as f32 / view_height;
+        match self.fit {
+            MaskFit::Fit => (scale_x, scale_y) = (scale_x.min(scale_y), scale_x.min(scale_y)),
+            MaskFit::Fill => (scale_x, scale_y) = (scale_x.max(scale_y), scale_x.max(scale_y)),
+            MaskFit::Stretch => {}
+        }
+
+        // Centers the shapes, like scaling an image mask does
+        Transform {
+            scale_x,
+            scale_y,
+            x: (width as f32 - view_width * scale_x) / 2.0 - x * scale_x,
+            y: (height as f32 - view_height * scale_y) / 2.0 - y * scale_y,
+        }
+    }
+
+    /// Draws the shapes into a mask of `width` by `height`, where black pixels are inside them
+    pub fn rasterize(&self, width: u32, height: u32) -> GrayImage {
+        let transform = self.transform(width, height);
+        // Curves are split into lines about two pixels long
+        let tolerance = 2.0 / transform.scale_x.max(transform.scale_y);
+
+        let mut mask = GrayImage::from_pixel(width, height, Luma([255]));
+
+        for shape in &self.shapes {
+            let polygons: Vec<Vec<(f32, f32)>> = shape
+                .polygons(tolerance)
+                .into_iter()
+                .map(|polygon| polygon.into_iter().map(|point| transform.apply(point)).collect())
+                .collect();
+
+            for y in 0..height {
+                let center_y = y as f32 + 0.5;
+
+                // Where the row's center crosses the edges, and in which direction
+                let mut crossings = Vec::new();
+                for polygon in &polygons {
+                    for (i, &(x0, y0)) in polygon.iter().enumerate() {
+                        let (x1, y1) = polygon[(i + 1) % polygon.len()];
+                        if (y0 <= center_y) != (y1 <= center_y) {
+                            let t = (center_y - y0) / (y1 - y0);
+                            crossings.push((x0 + t * (x1 - x0), if y1 > y0 { 1 } else { -1 }));
+                        }
+                    }
+                }
+                crossings.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+                let mut winding = 0;
+                for pair in crossings.windows(2) {
+                    winding += pair[0].1;
+                    let inside = if shape.even_odd { winding % 2 != 0 } else { winding != 0 };
+
+                    if inside {
+                        // Fills the pixels whose centers lie between the two crossings
+                        let start = (pair[0].0 - 0.5).ceil().clamp(0.0, width as f32) as u32;
+                        let end = (pair[1].0 - 0.5).ceil().clamp(0.0, width as f32) as u32;
+                        for x in start..end {
+                            mask.put_pixel(x, y, Luma([0]));
+                        }
+                    }
+                }
+            }
+        }
+
+        mask
+    }
+
+    /// Creates SVG paths of the shapes, placed like the rasterized mask of a word cloud of
+    /// `width` by `height` that is rendered at `scale`
+    pub(crate) fn svg_paths(&self, width: u32, height: u32, scale: f32) -> Vec<Path> {
+        let transform = self.transform(width, height);
+
+        self.shapes
+            .iter()
+            .map(|shape| {
+                let [a, b, c, d, e, f] = shape.matrix.0;
+                Path::new()
+                    .set("d", shape.data.clone())
+                    .set("fill-rule", if shape.even_odd { "evenodd" } else { "nonzero" })
+                    .set(
+                        "transform",
+                        format!(
+                            "translate({} {}) scale({} {}) matrix({} {} {} {} {} {})",
+                            transform.x * scale,
+                            transform.y * scale,
+                            transform.scale_x * scale,
+                            transform.scale_y * scale,
+                            a,
+                            b,
+                            c,
+                            d,
+                            e,
+                            f
+                        ),
+                    )
+            })
+            .collect()
+    }
+}
+
+/// Parses a length like `12` or `12px`, ignoring the unit
+fn parse_length(value: &str) -> f32 {
+    let value = value.trim();
+    let number_end = value
+        .find(|c: char| !(c.is_ascii_digit() || matches!(c, '.' | '-' | '+' | 'e' | 'E')))
+        .unwrap_or(value.len());
+    value[..number_end].parse().unwrap_or(0.0)
+}
+
+fn parse_numbers(value: &str) -> Result<Vec<f32>, VectorMaskError> {
+    value
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|number| !number.is_empty())
+        .map(|number| number.parse().map_err(|_| VectorMaskError::Numbers(value.to_string())))
+        .collect()
+}
+
+/// Parses a list of transform functions, like `translate(10 20) rotate(45)`
+fn parse_transform(value: &str) -> Result<Matrix, VectorMaskError> {
+    let invalid = || VectorMaskError::Transform(value.to_string());
+
+    let mut matrix = Matrix::IDENTITY;
+    let mut rest = value.trim();
+    while !rest.is_empty() {
+        let (name, after_name) = rest.split_once('(').ok_or_else(invalid)?;
+        let (arguments, after_arguments) = after_name.split_once(')').ok_or_else(invalid)?;
+        let arguments = parse_numbers(arguments).map_err(|_| invalid())?;
+
+        let function = match (name.trim(), &arguments[..]) {
+            ("matrix", &[a, b, c, d, e, f]) => Matrix([a, b, c, d, e, f]),
+            ("translate", &[x]) => Matrix([1.0, 0.0, 0.0, 1.0, x, 0.0]),
+            ("translate", &[x, y]) => Matrix([1.0, 0.0, 0.0, 1.0, x, y]),
+            ("scale", &[x]) => Matrix([x, 0.0, 0.0, x, 0.0, 0.0]),
+            ("scale", &[x, y]) => Matrix([x, 0.0, 0.0, y, 0.0, 0.0]),
+            ("rotate", &[angle]) => rotation(angle),
+            ("rotate", &[angle, x, y]) => Matrix([1.0, 0.0, 0.0, 1.0, x, y])
+                .then(&rotation(angle))
+                .then(&Matrix([1.0, 0.0, 0.0, 1.0, -x, -y])),
+            ("skewX", &[angle]) => Matrix([1.0, 0.0, angle.to_radians().tan(), 1.0, 0.0, 0.0]),
+            ("skewY", &[angle]) => Matrix([1.0, angle.to_radians().tan(), 0.0, 1.0, 0.0, 0.0]),
+            _ => return Err(invalid()),
+        };
+        matrix = matrix.then(&function);
+
+        rest = after_arguments.trim_start_matches(|c: char| c == ',' || c.is_whitespace());
+    }
+
+    Ok(matrix)
+}
+
+/// A rotation of `angle` degrees, clockwise in SVG's coordinates
+fn rotation(angle: f32) -> Matrix {
+    let (sin, cos) = angle.to_radians().sin_cos();
+    Matrix([cos, sin, -sin, cos, 0.0, 0.0])
+}
+
+/// Reads the `viewBox` of an `<svg>` element, falling back to its width and height
+fn parse_view_box(
+    attributes: &Attributes,
+) -> Result<Option<(f32, f32, f32, f32)>, VectorMaskError> {
+    if let Some(view_box) = attributes.get("viewBox") {
+        if let [x, y, width, height] = parse_numbers(view_box)?[..] {
+            return Ok(Some((x, y, width, height)));
+        }
+    }
+
+    let (Some(width), Some(height)) = (attributes.get("width"), attributes.get("height")) else {
+        return Ok(None);
+    };
+    Ok(Some((0.0, 0.0, parse_length(width), parse_length(height))))
+}
+
+/// The bounds of the shapes, as x, y, width and height
+fn bounds(shapes: &[Shape]) -> (f32, f32, f32, f32) {
+    let points = shapes.iter().flat_map(|shape| shape.polygons(f32::INFINITY));
+    let (mut min_x, mut min_y) = (f32::MAX, f32::MAX);
+    let (mut max_x, mut max_y) = (f32::MIN, f32::MIN);
+    for (x, y) in points.flatten() {
+        (min_x, min_y) = (min_x.min(x), min_y.min(y));
+        (max_x, max_y) = (max_x.max(x), max_y.max(y));
+    }
+
+    (min_x, min_y, max_x - min_x, max_y - min_y)
+}
+
+fn polygon(points: &str) -> Result<Data, VectorMaskError> {
+    let points = parse_numbers(points)?;
+    let mut pairs = points.chunks_exact(2);
+
+    let mut data = Data::new();
+    if let Some(first) = pairs.next() {
+        data = data.move_to((first[0], first[1]));
+    }
+    for pair in pairs {
+        data = data.line_to((pair[0], pair[1]));
+    }
+    Ok(data.close())
+}
+
+fn rect(x: f32, y: f32, width: f32, height: f32) -> Data {
+    Data::new()
+        .move_to((x, y))
+        .horizontal_line_by(width)
+        .vertical_line_by(height)
+        .horizontal_line_by(-width)
+        .close()
+}
+
+pub(crate) fn ellipse(cx: f32, cy: f32, rx: f32, ry: f32) -> Data {
+    Data::new()
+        .move_to((cx - rx, cy))
+        .elliptical_arc_by((rx, ry, 0, 0, 0, 2.0 * rx, 0))
+        .elliptical_arc_by((rx, ry, 0, 0, 0, -2.0 * rx, 0))
+        .close()
+}
+
+/// How many lines a curve whose control points are `length` apart is split into
+fn segments(length: f32, tolerance: f32) -> usize {
+    (length / tolerance).ceil().clamp(8.0, 1024.0) as usize
+}
+
+fn distance(a: (f32, f32), b: (f32, f32)) -> f32 {
+    (a.0 - b.0).hypot(a.1 - b.1)
+}
+
+/// Turns path data into polygons, splitting curves into lines about `tolerance` long
+fn flatten(data: &Data, tolerance: f32) -> Vec<Vec<(f32, f32)>> {
+    let mut polygons = Vec::new();
+    let mut polygon = Vec::new();
+
+    let mut current = (0.0, 0.0);
+    let mut start = (0.0, 0.0);
+    // The last control point of the previous curve, which smooth curves mirror
+    let mut cubic_control: Option<(f32, f32)> = None;
+    let mut quadratic_control: Option<(f32, f32)> = None;
+
+    for command in data.iter() {
+        let resolve = |position: &Position, current: (f32, f32), x: f32, y: f32| match position {
+            Position::Absolute => (x, y),
+            Position::Relative => (current.0 + x, current.1 + y),
+        };
+        let mirror = |control: Option<(f32, f32)>, current: (f32, f32)| {
+            control.map_or(current, |(x, y)| (2.0 * current.0 - x, 2.0 * current.1 - y))
+        };
+
+        // Continues the previous subpath from its start, if it was closed
+        if polygon.is_empty() && !matches!(command, Command::Move(..)) {
+            polygon.push(start);
+        }
+
+        match command {
+            Command::Move(position, parameters) => {
+                for (i, pair) in parameters.chunks_exact(2).enumerate() {
+                    current = resolve(position, current, pair[0], pair[1]);
+                    if i == 0 {
+                        if polygon.len() > 1 {
+                            polygons.push(std::mem::take(&mut polygon));
+                        }
+                        polygon.clear();
+                        start = current;
+                    }
+                    polygon.push(current);
+                }
+            }
+            Command::Line(position, parameters) => {
+                for pair in parameters.chunks_exact(2) {
+                    current = resolve(position, current, pair[0], pair[1]);
+                    polygon.push(current);
+                }
+            }
+            Command::HorizontalLine(position, parameters) => {
+                for x in parameters.iter() {
+                    current = match position {
+                        Position::Absolute => (*x, current.1),
+                        Position::Relative => (current.0 + x, current.1),
+                    };
+                    polygon.push(current);
+                }
+            }
+            Command::VerticalLine(position, parameters) => {
+                for y in parameters.iter() {
+                    current = match position {
+                        Position::Absolute => (current.0, *y),
+                        Position::Relative => (current.0, current.1 + y),
+                    };
+                    polygon.push(current);
+                }
+            }
+            Command::CubicCurve(position, parameters) => {
+                for p in parameters.chunks_exact(6) {
+                    let control_1 = resolve(position, current, p[0], p[1]);
+                    let control_2 = resolve(position, current, p[2], p[3]);
+                    let end = resolve(position, current, p[4], p[5]);
+                    cubic(&mut polygon, [current, control_1, control_2, end], tolerance);
+                    (current, cubic_control) = (end, Some(control_2));
+                }
+            }
+            Command::SmoothCubicCurve(position, parameters) => {
+                for p in parameters.chunks_exact(4) {
+                    let control_1 = mirror(cubic_control, current);
+                    let control_2 = resolve(position, current, p[0], p[1]);
+                    let end = resolve(position, current, p[2], p[3]);
+                    cubic(&mut polygon, [current, control_1, control_2, end], tolerance);
+                    (current, cubic_control) = (end, Some(control_2));
+                }
+            }
+            Command::QuadraticCurve(position, parameters) => {
+                for p in parameters.chunks_exact(4) {
+                    let control = resolve(position, current, p[0], p[1]);
+                    let end = resolve(position, current, p[2], p[3]);
+                    quadratic(&mut polygon, [current, control, end], tolerance);
+                    (current, quadratic_control) = (end, Some(control));
+                }
+            }
+            Command::SmoothQuadraticCurve(position, parameters) => {
+                for p in parameters.chunks_exact(2) {
+                    let control = mirror(quadratic_control, current);
+                    let end = resolve(position, current, p[0], p[1]);
+                    quadratic(&mut polygon, [current, control, end], tolerance);
+                    (current, quadratic_control) = (end, Some(control));
+                }
+            }
+            Command::EllipticalArc(position, parameters) => {
+                for p in parameters.chunks_exact(7) {
+                    let end = resolve(position, current, p[5], p[6]);
+                    let arc = Arc {
+                        radii: (p[0], p[1]),
+                        rotation: p[2],
+                        large_arc: p[3] != 0.0,
+                        sweep: p[4] != 0.0,
+                    };
+                    arc.flatten(&mut polygon, current, end, tolerance);
+                    current = end;
+                }
+            }
+            Command::Close => {
+                if polygon.len() > 1 {
+                    polygons.push(std::mem::take(&mut polygon));
+                }
+                polygon.clear();
+                current = start;
+            }
+        }
+
+        if !matches!(command, Command::CubicCurve(..) | Command::SmoothCubicCurve(..)) {
+            cubic_control = None;
+        }
+        if !matches!(command, Command::QuadraticCurve(..) | Command::SmoothQuadraticCurve(..)) {
+            quadratic_control = None;
+        }
+    }
+
+    if polygon.len() > 1 {
+        polygons.push(polygon);
+    }
+
+    polygons
+}
+
+fn cubic(polygon: &mut Vec<(f32, f32)>, points: [(f32, f32); 4], tolerance: f32) {
+    let [p0, p1, p2, p3] = points;
+    let length = distance(p0, p1) + distance(p1, p2) + distance(p2, p3);
+    let n = segments(length, tolerance);
+
+    for i in 1..=n {
+        let t = i as f32 / n as f32;
+        let u = 1.0 - t;
+        let (a, b, c, d) = (u * u * u, 3.0 * u * u * t, 3.0 * u * t * t, t * t * t);
+        polygon.push((
+            a * p0.0 + b * p1.0 + c * p2.0 + d * p3.0,
+            a * p0.1 + b * p1.1 + c * p2.1 + d * p3.1,
+        ));
+    }
+}
+
+fn quadratic(polygon: &mut Vec<(f32, f32)>, points: [(f32, f32); 3], tolerance: f32) {
+    let [p0, p1, p2] = points;
+    let length = distance(p0, p1) + distance(p1, p2);
+    let n = segments(length, tolerance);
+
+    for i in 1..=n {
+        let t = i as f32 / n as f32;
+        let u = 1.0 - t;
+        let (a, b, c) = (u * u, 2.0 * u * t, t * t);
+        polygon.push((a * p0.0 + b * p1.0 + c * p2.0, a * p0.1 + b * p1.1 + c * p2.1));
+    }
+}
+
+/// An elliptical arc, as described by SVG path data
+struct Arc {
+    radii: (f32, f32),
+    /// The rotation of the ellipse's x axis in degrees
+    rotation: f32,
+    large_arc: bool,
+    sweep: bool,
+}
+
+impl Arc {
+    /// Adds the arc from `from` to `to` to the polygon, by converting it to the center
+    /// parameterization described at https://www.w3.org/TR/SVG/implnote.html#ArcImplementationNotes
+    fn flatten(
+        &self,
+        polygon: &mut Vec<(f32, f32)>,
+        from: (f32, f32),
+        to: (f32, f32),
+        tolerance: f32,
+    ) {
+        let (mut rx, mut ry) = (self.radii.0.abs(), self.radii.1.abs());
+        if from == to {
+            return;
+        }
+        if rx == 0.0 || ry == 0.0 {
+            polygon.push(to);
+            return;
+        }
+
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        let (half_dx, half_dy) = ((from.0 - to.0) / 2.0, (from.1 - to.1) / 2.0);
+        let x1 = cos * half_dx + sin * half_dy;
+        let y1 = -sin * half_dx + cos * half_dy;
+
+        // Scales up radii that are too small to reach the end point
+        let lambda = (x1 * x1) / (rx * rx) + (y1 * y1) / (ry * ry);
+        if lambda > 1.0 {
+            rx *= lambda.sqrt();
+            ry *= lambda.sqrt();
+        }
+
+        let numerator = rx * rx * ry * ry - rx * rx * y1 * y1 - ry * ry * x1 * x1;
+        let denominator = rx * rx * y1 * y1 + ry * ry * x1 * x1;
+        let sign = if self.large_arc == self.sweep { -1.0 } else { 1.0 };
+        let coefficient = sign * (numerator / denominator).max(0.0).sqrt();
+        let center_x1 = coefficient * rx * y1 / ry;
+        let center_y1 = -coefficient * ry * x1 / rx;
+
+        let center_x = cos * center_x1 - sin * center_y1 + (from.0 + to.0) / 2.0;
+        let center_y = sin * center_x1 + cos * center_y1 + (from.1 + to.1) / 2.0;
+
+        let angle = |x: f32, y: f32| y.atan2(x);
+        let start_angle = angle((x1 - center_x1) / rx, (y1 - center_y1) / ry);
+        let end_angle = angle((-x1 - center_x1) / rx, (-y1 - center_y1) / ry);
+
+        let mut sweep_angle = end_angle - start_angle;
+        if self.sweep && sweep_angle < 0.0 {
+            sweep_angle += TAU;
+        } else if !self.sweep && sweep_angle > 0.0 {
+            sweep_angle -= TAU;
+        }
+
+        let n = segments(sweep_angle.abs() * rx.max(ry), tolerance);
+        for i in 1..=n {
+            let (sin_t, cos_t) = (start_angle + sweep_angle * i as f32 / n as f32).sin_cos();
+            polygon.push((
+                center_x + rx * cos_t * cos - ry * sin_t * sin,
+                center_y + rx * cos_t * sin + ry * sin_t * cos,
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_inside(mask: &GrayImage, x: u32, y: u32) -> bool {
+        mask.get_pixel(x, y).0[0] == 0
+    }
+
+    #[test]
+    fn rasterizes_path_data() {
+        // A square with a triangular notch cut out of its top edge
+        let mask = VectorMask::from_path_data("M0 0 L4 4 L8 0 V8 H0 Z").unwrap().rasterize(16, 16);
+
+        assert!(is_inside(&mask, 1, 14));
+        assert!(is_inside(&mask, 14, 14));
+        assert!(!is_inside(&mask, 8, 2));
+        assert!(is_inside(&mask, 8, 12));
+    }
+
+    #[test]
+    fn svg_file_with_hole() {
+        let svg = r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 100 50">
+            <path fill-rule="evenodd" d="M10 10 h30 v30 h-30 z M20 20 h10 v10 h-10 z"/>
+            <circle cx="75" cy="25" r="20"/>
+        </svg>"#;
+        let mask = VectorMask::from_svg(svg).unwrap().rasterize(200, 100);
+
+        assert!(is_inside(&mask, 30, 30));
+        assert!(!is_inside(&mask, 50, 50));
+        assert!(!is_inside(&mask, 2, 2));
+        assert!(is_inside(&mask, 150, 50));
+        assert!(is_inside(&mask, 150, 12));
+        assert!(!is_inside(&mask, 115, 12));
+    }
+
+    #[test]
+    fn transforms_are_applied() {
+        // The square is moved into the right half of the view box by its group
+        let svg = r#"<svg viewBox="0 0 100 50">
+            <g transform="translate(50 0)">
+                <rect transform="scale(2)" width="20" height="20"/>
+            </g>
+        </svg>"#;
+        let mask = VectorMask::from_svg(svg).unwrap().rasterize(100, 50);
+
+        assert!(is_inside(&mask, 60, 10));
+        assert!(is_inside(&mask, 85, 35));
+        assert!(!is_inside(&mask, 10, 10));
+        assert!(!is_inside(&mask, 95, 45));
+    }
+
+    #[test]
+    fn invalid_masks_are_errors() {
+        assert!(matches!(
+            VectorMask::from_path_data("M0 0 L4 x"),
+            Err(VectorMaskError::PathData(_))
+        ));
+        assert!(matches!(
+            VectorMask::from_svg(r#"<svg><rect transform="spin(4)" width="1" height="1"/></svg>"#),
+            Err(VectorMaskError::Transform(_))
+        ));
+        assert!(matches!(
+            VectorMask::from_svg(r#"<svg><polygon points="0,0 1,x"/></svg>"#),
+            Err(VectorMaskError::Numbers(_))
+        ));
+        assert!(matches!(VectorMask::from_svg("<svg></svg>"), Err(VectorMaskError::NoShapes)));
+        assert!(matches!(VectorMask::from_path_data("M0 0 L4 0"), Err(VectorMaskError::NoArea)));
+    }
+}
@@ -30,14 +30,14 @@ pub struct Word {
     pub font_size: u32,
     pub rect_width: u32,
     pub rect_height: u32,
-    pub rotation: u32,
+    pub rotation: f32,
 }
 
 #[derive(Serialize)]
 pub struct PlaceWord {
     pub text: String,
     pub font_size: u32,
-    pub rotation: u32,
+    pub rotation: f32,
     pub x: u32,
     pub y: u32,
 }